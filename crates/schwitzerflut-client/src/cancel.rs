@@ -0,0 +1,101 @@
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Granularity in which [`CancellationToken::sleep`] checks for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Cheaply cloneable flag used to cooperatively stop all shard threads
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Cancels the token when the process receives SIGINT or SIGTERM. A second signal exits right
+    /// away, in case stopping hangs.
+    pub fn cancel_on_signals(&self) -> io::Result<()> {
+        for signal in [SIGINT, SIGTERM] {
+            // only exits if the token was already cancelled, so it has to be registered first
+            signal_hook::flag::register_conditional_shutdown(signal, 1, self.0.clone())?;
+            signal_hook::flag::register(signal, self.0.clone())?;
+        }
+
+        Ok(())
+    }
+
+    /// Cancels the token once `duration` has passed, on a background thread.
+    pub fn cancel_after(&self, duration: Duration) {
+        let token = self.clone();
+
+        thread::spawn(move || {
+            token.sleep(duration);
+            token.cancel();
+        });
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Sleeps for the given duration, waking up early if the token is cancelled.
+    ///
+    /// Returns whether the full duration has elapsed.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+
+        loop {
+            if self.is_cancelled() {
+                return false;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cancel::CancellationToken;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_sleep_elapses() {
+        let token = CancellationToken::default();
+
+        assert!(token.sleep(Duration::from_millis(10)));
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn test_sleep_returns_early_when_cancelled() {
+        let token = CancellationToken::default();
+        let clone = token.clone();
+        let start = Instant::now();
+
+        std::thread::spawn(move || clone.cancel());
+
+        assert!(!token.sleep(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_cancel_after() {
+        let token = CancellationToken::default();
+
+        token.cancel_after(Duration::from_millis(20));
+
+        assert!(!token.is_cancelled());
+        assert!(!token.sleep(Duration::from_secs(10)));
+    }
+}
@@ -0,0 +1,170 @@
+use image::{Rgba, RgbaImage};
+
+/// Rec. 709 weights of the red, green and blue channels in the perceived brightness
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Colour correction applied to every pixel, leaving alpha untouched
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Adjustment {
+    /// Values above 1 brighten the midtones, values below 1 darken them
+    Gamma(f32),
+    /// Multiplies all channels, 1 leaves the image unchanged
+    Brightness(f32),
+    /// Spreads channels away from or towards the middle grey, 1 leaves the image unchanged
+    Contrast(f32),
+    /// 0 removes all colour, 1 leaves the image unchanged, above 1 intensifies colours
+    Saturation(f32),
+    /// Rotates hues by this many degrees around the colour wheel
+    HueRotate(f32),
+    Invert,
+    Grayscale,
+}
+
+impl Adjustment {
+    pub fn apply(&self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            self.apply_pixel(pixel);
+        }
+    }
+
+    fn apply_pixel(&self, pixel: &mut Rgba<u8>) {
+        let rgb = [pixel[0], pixel[1], pixel[2]].map(|channel| channel as f32 / 255.0);
+
+        let rgb = match *self {
+            Self::Gamma(gamma) => rgb.map(|channel| channel.powf(1.0 / gamma.max(f32::EPSILON))),
+            Self::Brightness(factor) => rgb.map(|channel| channel * factor),
+            Self::Contrast(factor) => rgb.map(|channel| (channel - 0.5) * factor + 0.5),
+            Self::Saturation(factor) => saturate(rgb, factor),
+            Self::HueRotate(degrees) => hue_rotate(rgb, degrees),
+            Self::Invert => rgb.map(|channel| 1.0 - channel),
+            Self::Grayscale => saturate(rgb, 0.0),
+        };
+
+        for (channel, value) in pixel.0.iter_mut().zip(rgb) {
+            *channel = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+fn luma(rgb: [f32; 3]) -> f32 {
+    rgb.iter()
+        .zip(LUMA)
+        .map(|(channel, weight)| channel * weight)
+        .sum()
+}
+
+fn saturate(rgb: [f32; 3], factor: f32) -> [f32; 3] {
+    let luma = luma(rgb);
+    rgb.map(|channel| luma + (channel - luma) * factor)
+}
+
+/// Rotates around the grey axis, keeping the luma, like the CSS `hue-rotate()` filter
+fn hue_rotate(rgb: [f32; 3], degrees: f32) -> [f32; 3] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let [lr, lg, lb] = LUMA;
+
+    let matrix = [
+        [
+            lr + cos * (1.0 - lr) - sin * lr,
+            lg - cos * lg - sin * lg,
+            lb - cos * lb + sin * (1.0 - lb),
+        ],
+        [
+            lr - cos * lr + sin * 0.143,
+            lg + cos * (1.0 - lg) + sin * 0.140,
+            lb - cos * lb - sin * 0.283,
+        ],
+        [
+            lr - cos * lr - sin * (1.0 - lr),
+            lg - cos * lg + sin * lg,
+            lb + cos * (1.0 - lb) + sin * lb,
+        ],
+    ];
+
+    matrix.map(|row| {
+        row.iter()
+            .zip(rgb)
+            .map(|(weight, channel)| weight * channel)
+            .sum()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::adjust::Adjustment;
+    use image::{Rgba, RgbaImage};
+
+    fn adjust(color: [u8; 4], adjustment: Adjustment) -> [u8; 4] {
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba(color));
+        adjustment.apply(&mut image);
+
+        image.get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn test_gamma() {
+        assert_eq!(
+            adjust([64, 0, 255, 7], Adjustment::Gamma(1.0)),
+            [64, 0, 255, 7]
+        );
+        assert_eq!(
+            adjust([64, 0, 255, 7], Adjustment::Gamma(2.0)),
+            [128, 0, 255, 7]
+        );
+    }
+
+    #[test]
+    fn test_brightness() {
+        assert_eq!(
+            adjust([100, 200, 0, 255], Adjustment::Brightness(1.5)),
+            [150, 255, 0, 255]
+        );
+    }
+
+    #[test]
+    fn test_contrast() {
+        assert_eq!(
+            adjust([64, 191, 128, 255], Adjustment::Contrast(0.0)),
+            [128, 128, 128, 255]
+        );
+        assert_eq!(
+            adjust([100, 155, 0, 255], Adjustment::Contrast(1.5)),
+            [86, 169, 0, 255]
+        );
+    }
+
+    #[test]
+    fn test_saturation_and_grayscale() {
+        let gray = adjust([255, 0, 0, 255], Adjustment::Grayscale);
+
+        assert_eq!(gray, [54, 54, 54, 255]);
+        assert_eq!(adjust([255, 0, 0, 255], Adjustment::Saturation(0.0)), gray);
+        assert_eq!(
+            adjust([200, 100, 50, 255], Adjustment::Saturation(1.0)),
+            [200, 100, 50, 255]
+        );
+    }
+
+    #[test]
+    fn test_hue_rotate() {
+        assert_eq!(
+            adjust([200, 100, 50, 255], Adjustment::HueRotate(0.0)),
+            [200, 100, 50, 255]
+        );
+        assert_eq!(
+            adjust([200, 100, 50, 255], Adjustment::HueRotate(360.0)),
+            [200, 100, 50, 255]
+        );
+
+        let [r, g, b, _] = adjust([255, 0, 0, 255], Adjustment::HueRotate(120.0));
+        assert!(g > r && g > b);
+    }
+
+    #[test]
+    fn test_invert() {
+        assert_eq!(
+            adjust([0, 100, 255, 9], Adjustment::Invert),
+            [255, 155, 0, 9]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use image::{Rgba, RgbaImage};
+use schwitzerflut_protocol::color::{blend, Color, RgbColor, RgbaColor};
+
+/// Colour that is treated as transparent, e.g. the green of a green screen
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChromaKey {
+    pub color: RgbColor,
+    /// Largest difference in any channel that still counts as the key colour
+    pub tolerance: u8,
+}
+
+impl ChromaKey {
+    pub fn matches(&self, pixel: &Rgba<u8>) -> bool {
+        let key = [self.color.r, self.color.g, self.color.b];
+
+        key.iter()
+            .zip(pixel.0)
+            .all(|(&key, channel)| key.abs_diff(channel) <= self.tolerance)
+    }
+
+    /// Makes every pixel matching the key fully transparent.
+    pub fn apply(&self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut().filter(|pixel| self.matches(pixel)) {
+            pixel[3] = 0;
+        }
+    }
+}
+
+/// Decides which pixels are transparent and how the others are sent
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Transparency {
+    /// Pixels with a lower alpha are transparent
+    pub threshold: u8,
+    /// Colour translucent pixels are blended onto, so they can be sent as plain RGB
+    pub background: Option<RgbColor>,
+}
+
+impl Default for Transparency {
+    fn default() -> Self {
+        Self {
+            threshold: 1,
+            background: None,
+        }
+    }
+}
+
+impl Transparency {
+    pub fn is_transparent(&self, pixel: &Rgba<u8>) -> bool {
+        pixel[3] < self.threshold
+    }
+
+    /// The colour to send for a pixel
+    pub fn color(&self, pixel: &Rgba<u8>) -> Color {
+        let [r, g, b, alpha] = pixel.0;
+        let alpha = if self.is_transparent(pixel) { 0 } else { alpha };
+
+        match self.background {
+            Some(background) => Color::Rgb(blend(background, RgbColor::new(r, g, b), alpha)),
+            None => Color::Rgba(RgbaColor::new(RgbColor::new(r, g, b), alpha)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::alpha::{ChromaKey, Transparency};
+    use image::{Rgba, RgbaImage};
+    use schwitzerflut_protocol::color::{Color, RgbColor, RgbaColor};
+
+    #[test]
+    fn test_threshold() {
+        let transparency = Transparency {
+            threshold: 128,
+            background: None,
+        };
+
+        assert!(transparency.is_transparent(&Rgba([255, 0, 0, 127])));
+        assert!(!transparency.is_transparent(&Rgba([255, 0, 0, 128])));
+        assert_eq!(
+            transparency.color(&Rgba([255, 0, 0, 200])),
+            Color::Rgba(RgbaColor::new(RgbColor::new(255, 0, 0), 200))
+        );
+    }
+
+    #[test]
+    fn test_default_only_skips_fully_transparent_pixels() {
+        let transparency = Transparency::default();
+
+        assert!(transparency.is_transparent(&Rgba([255, 0, 0, 0])));
+        assert!(!transparency.is_transparent(&Rgba([255, 0, 0, 1])));
+    }
+
+    #[test]
+    fn test_premultiply_against_background() {
+        let transparency = Transparency {
+            threshold: 16,
+            background: Some(RgbColor::new(0, 0, 255)),
+        };
+
+        assert_eq!(
+            transparency.color(&Rgba([255, 0, 0, 128])),
+            Color::Rgb(RgbColor::new(128, 0, 127))
+        );
+        // below the threshold only the background is left
+        assert_eq!(
+            transparency.color(&Rgba([255, 0, 0, 15])),
+            Color::Rgb(RgbColor::new(0, 0, 255))
+        );
+    }
+
+    #[test]
+    fn test_chroma_key() {
+        let key = ChromaKey {
+            color: RgbColor::new(0, 255, 0),
+            tolerance: 10,
+        };
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([5, 250, 10, 255]));
+        image.put_pixel(1, 0, Rgba([20, 250, 10, 255]));
+
+        key.apply(&mut image);
+
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+        assert_eq!(image.get_pixel(1, 0)[3], 255);
+    }
+}
@@ -0,0 +1,121 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageFormat, ImageReader, ImageResult};
+use std::io::{BufRead, Seek};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long to show frames without a delay, like browsers do
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Single image of an animation
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub image: DynamicImage,
+    /// How long the frame is shown before the next one
+    pub delay: Duration,
+}
+
+impl From<image::Frame> for Frame {
+    fn from(frame: image::Frame) -> Self {
+        let delay = Duration::from(frame.delay());
+
+        Self {
+            delay: match delay.is_zero() {
+                true => DEFAULT_FRAME_DELAY,
+                false => delay,
+            },
+            image: DynamicImage::ImageRgba8(frame.into_buffer()),
+        }
+    }
+}
+
+/// Decodes all frames of an animated GIF or PNG.
+///
+/// Frames are composed onto each other, so every one of them is a complete image. Images of other
+/// formats and PNGs without an animation have no frames.
+pub fn open_frames(path: &Path) -> ImageResult<Vec<Frame>> {
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+
+    match reader.format() {
+        Some(format) => decode_frames(reader.into_inner(), format),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Decodes all frames of an animation in the given format, see [`open_frames`]
+pub fn decode_frames(reader: impl BufRead + Seek, format: ImageFormat) -> ImageResult<Vec<Frame>> {
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader)?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader)?;
+            if !decoder.is_apng()? {
+                return Ok(Vec::new());
+            }
+
+            decoder.apng()?.into_frames()
+        }
+        _ => return Ok(Vec::new()),
+    };
+
+    frames.map(|frame| frame.map(Frame::from)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::animation::decode_frames;
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, DynamicImage, Frame, ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    fn gif(frames: &[(Rgba<u8>, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = GifEncoder::new(&mut data);
+
+        for &(color, delay) in frames {
+            encoder
+                .encode_frame(Frame::from_parts(
+                    RgbaImage::from_pixel(2, 2, color),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(delay, 1),
+                ))
+                .unwrap();
+        }
+        drop(encoder);
+
+        data
+    }
+
+    #[test]
+    fn test_decode_gif() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let data = gif(&[(red, 200), (blue, 50), (red, 0)]);
+
+        let frames = decode_frames(Cursor::new(data), ImageFormat::Gif).unwrap();
+
+        assert_eq!(
+            frames.iter().map(|frame| frame.delay).collect::<Vec<_>>(),
+            vec![
+                Duration::from_millis(200),
+                Duration::from_millis(50),
+                Duration::from_millis(100)
+            ]
+        );
+        assert_eq!(frames[1].image.to_rgba8().get_pixel(1, 1), &blue);
+    }
+
+    #[test]
+    fn test_still_png_has_no_frames() {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(2, 2))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+
+        assert!(decode_frames(Cursor::new(data), ImageFormat::Png)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,400 @@
+use crate::command_generator::exclude::Region;
+use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+use schwitzerflut_protocol::color::Color;
+use schwitzerflut_protocol::command::{Command, SetPixelCommand};
+use schwitzerflut_protocol::coordinates::Coordinates;
+
+/// Commands of `first`, then those of `second`
+pub struct Chain<A, B>
+where
+    A: IndexedCommandGenerator,
+    B: IndexedCommandGenerator,
+{
+    first: A,
+    second: B,
+}
+
+impl<A: IndexedCommandGenerator, B: IndexedCommandGenerator> Chain<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A, B> CommandGenerator for Chain<A, B>
+where
+    A: IndexedCommandGenerator,
+    B: IndexedCommandGenerator,
+{
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        self.first.commands().chain(self.second.commands())
+    }
+}
+
+impl<A, B> IndexedCommandGenerator for Chain<A, B>
+where
+    A: IndexedCommandGenerator,
+    B: IndexedCommandGenerator,
+{
+    fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        match index.checked_sub(self.first.len()) {
+            None => self.first.command_at(index),
+            Some(index) => self.second.command_at(index),
+        }
+    }
+}
+
+/// Alternates between the commands of `first` and `second`, continuing with the rest of the
+/// longer one once the shorter one runs out
+pub struct Interleave<A, B>
+where
+    A: IndexedCommandGenerator,
+    B: IndexedCommandGenerator,
+{
+    first: A,
+    second: B,
+}
+
+impl<A: IndexedCommandGenerator, B: IndexedCommandGenerator> Interleave<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A, B> CommandGenerator for Interleave<A, B>
+where
+    A: IndexedCommandGenerator,
+    B: IndexedCommandGenerator,
+{
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (0..self.len()).map(|index| self.command_at(index))
+    }
+}
+
+impl<A, B> IndexedCommandGenerator for Interleave<A, B>
+where
+    A: IndexedCommandGenerator,
+    B: IndexedCommandGenerator,
+{
+    fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        let shorter = self.first.len().min(self.second.len());
+
+        match index < 2 * shorter {
+            true if index.is_multiple_of(2) => self.first.command_at(index / 2),
+            true => self.second.command_at(index / 2),
+            false if self.first.len() > shorter => self.first.command_at(index - shorter),
+            false => self.second.command_at(index - shorter),
+        }
+    }
+}
+
+/// Moves every pixel by `offset`, leaving out those that would move past the largest coordinate.
+///
+/// Like [`Clip`], the remaining commands are looked up once when created.
+pub struct Translate<G>
+where
+    G: IndexedCommandGenerator,
+{
+    generator: G,
+    offset: Coordinates,
+    kept: Vec<usize>,
+}
+
+impl<G: IndexedCommandGenerator> Translate<G> {
+    pub fn new(generator: G, offset: Coordinates) -> Self {
+        let kept = kept(&generator, |command| {
+            translate(command.coordinates, offset).is_some()
+        });
+
+        Self {
+            generator,
+            offset,
+            kept,
+        }
+    }
+}
+
+fn translate(coordinates: Coordinates, offset: Coordinates) -> Option<Coordinates> {
+    Some(Coordinates::new(
+        coordinates.x.checked_add(offset.x)?,
+        coordinates.y.checked_add(offset.y)?,
+    ))
+}
+
+impl<G> CommandGenerator for Translate<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (0..self.len()).map(|index| self.command_at(index))
+    }
+}
+
+impl<G> IndexedCommandGenerator for Translate<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn len(&self) -> usize {
+        self.kept.len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        match self.generator.command_at(self.kept[index]) {
+            Command::SetPixel(command) => Command::SetPixel(SetPixelCommand::new(
+                translate(command.coordinates, self.offset).expect("only pixels that fit are kept"),
+                command.color,
+            )),
+            command => command,
+        }
+    }
+}
+
+/// Indices of the commands to keep, pixels are kept if `keep` returns true for them
+pub(crate) fn kept(
+    generator: &impl IndexedCommandGenerator,
+    keep: impl Fn(&SetPixelCommand) -> bool,
+) -> Vec<usize> {
+    (0..generator.len())
+        .filter(|&index| match generator.command_at(index) {
+            Command::SetPixel(command) => keep(&command),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Only keeps the pixels inside a region of the canvas.
+///
+/// The remaining commands are looked up once when created, so they can still be accessed by index.
+pub struct Clip<G>
+where
+    G: IndexedCommandGenerator,
+{
+    generator: G,
+    kept: Vec<usize>,
+}
+
+impl<G: IndexedCommandGenerator> Clip<G> {
+    pub fn new(generator: G, region: &Region) -> Self {
+        let kept = kept(&generator, |command| region.contains(&command.coordinates));
+
+        Self { generator, kept }
+    }
+}
+
+impl<G> CommandGenerator for Clip<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (0..self.len()).map(|index| self.command_at(index))
+    }
+}
+
+impl<G> IndexedCommandGenerator for Clip<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn len(&self) -> usize {
+        self.kept.len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        self.generator.command_at(self.kept[index])
+    }
+}
+
+/// Only keeps the pixels whose colour matches, e.g. to leave out the background
+pub struct FilterColor<G>
+where
+    G: IndexedCommandGenerator,
+{
+    generator: G,
+    kept: Vec<usize>,
+}
+
+impl<G: IndexedCommandGenerator> FilterColor<G> {
+    pub fn new(generator: G, matches: impl Fn(&Color) -> bool) -> Self {
+        let kept = kept(&generator, |command| matches(&command.color));
+
+        Self { generator, kept }
+    }
+}
+
+impl<G> CommandGenerator for FilterColor<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (0..self.len()).map(|index| self.command_at(index))
+    }
+}
+
+impl<G> IndexedCommandGenerator for FilterColor<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn len(&self) -> usize {
+        self.kept.len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        self.generator.command_at(self.kept[index])
+    }
+}
+
+/// All commands again and again, `times` times in total
+pub struct Repeat<G>
+where
+    G: IndexedCommandGenerator,
+{
+    generator: G,
+    times: usize,
+}
+
+impl<G: IndexedCommandGenerator> Repeat<G> {
+    pub fn new(generator: G, times: usize) -> Self {
+        Self { generator, times }
+    }
+}
+
+impl<G> CommandGenerator for Repeat<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (0..self.times).flat_map(|_| self.generator.commands())
+    }
+}
+
+impl<G> IndexedCommandGenerator for Repeat<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn len(&self) -> usize {
+        self.generator.len() * self.times
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        assert!(index < self.len(), "index {index} out of bounds");
+        self.generator.command_at(index % self.generator.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::combinator::{
+        Chain, Clip, FilterColor, Interleave, Repeat, Translate,
+    };
+    use crate::command_generator::fill::Fill;
+    use crate::command_generator::shard::Shard;
+    use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+    use schwitzerflut_protocol::color::{Color, RgbColor};
+    use schwitzerflut_protocol::command::Command;
+    use schwitzerflut_protocol::coordinates::Coordinates;
+
+    const RED: Color = Color::Rgb(RgbColor { r: 255, g: 0, b: 0 });
+    const BLUE: Color = Color::Rgb(RgbColor { r: 0, g: 0, b: 255 });
+
+    /// A `width` by `height` rectangle at `(x, y)`
+    fn fill(x: u32, y: u32, width: u32, height: u32, color: Color) -> Fill {
+        Fill::new(Coordinates::new(x, y), (width, height), color)
+    }
+
+    /// Coordinates of all pixels, checking that indexed access matches iterating
+    fn pixels(generator: &impl IndexedCommandGenerator) -> Vec<(u32, u32)> {
+        let commands = generator.commands().collect::<Vec<_>>();
+        assert_eq!(commands.len(), generator.len());
+        for (index, command) in commands.iter().enumerate() {
+            assert_eq!(command, &generator.command_at(index));
+        }
+
+        commands
+            .into_iter()
+            .map(|command| match command {
+                Command::SetPixel(command) => (command.coordinates.x, command.coordinates.y),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chain() {
+        let chain = Chain::new(fill(0, 0, 2, 1, RED), fill(5, 5, 1, 1, BLUE));
+
+        assert_eq!(pixels(&chain), vec![(0, 0), (1, 0), (5, 5)]);
+    }
+
+    #[test]
+    fn test_interleave() {
+        let interleave = Interleave::new(fill(0, 0, 4, 1, RED), fill(0, 1, 2, 1, BLUE));
+        assert_eq!(
+            pixels(&interleave),
+            vec![(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (3, 0)]
+        );
+
+        let interleave = Interleave::new(fill(0, 0, 1, 1, RED), fill(0, 1, 3, 1, BLUE));
+        assert_eq!(pixels(&interleave), vec![(0, 0), (0, 1), (1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn test_translate() {
+        let translate = Translate::new(fill(1, 2, 2, 1, RED), Coordinates::new(10, 20));
+
+        assert_eq!(pixels(&translate), vec![(11, 22), (12, 22)]);
+
+        // the second pixel would move past the edge
+        let translate = Translate::new(fill(u32::MAX - 1, 0, 2, 1, RED), Coordinates::new(1, 0));
+        assert_eq!(pixels(&translate), vec![(u32::MAX, 0)]);
+    }
+
+    #[test]
+    fn test_clip() {
+        let clip = Clip::new(fill(0, 0, 4, 4, RED), &"2x2+1+1".parse().unwrap());
+
+        assert_eq!(pixels(&clip), vec![(1, 1), (2, 1), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_filter_color() {
+        let scene = Chain::new(fill(0, 0, 2, 1, RED), fill(0, 1, 2, 1, BLUE));
+        let filter = FilterColor::new(scene, |color| color == &BLUE);
+
+        assert_eq!(pixels(&filter), vec![(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let repeat = Repeat::new(fill(0, 0, 2, 1, RED), 3);
+        assert_eq!(pixels(&repeat), [(0, 0), (1, 0)].repeat(3));
+
+        assert!(Repeat::new(fill(0, 0, 2, 1, RED), 0).is_empty());
+    }
+
+    #[test]
+    fn test_composed_scene_can_be_sharded() {
+        // a frame around a clipped square, drawn twice and split between two shards
+        let frame = Chain::new(fill(0, 0, 4, 1, BLUE), fill(0, 3, 4, 1, BLUE));
+        let square = Clip::new(
+            Translate::new(fill(0, 0, 4, 4, RED), Coordinates::new(1, 1)),
+            &"2x2+1+1".parse().unwrap(),
+        );
+        let scene = Repeat::new(Interleave::new(frame, square), 2);
+
+        let mut all = (0..2)
+            .flat_map(|shard| Shard::new(&scene, shard, 2).commands().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        all.sort_by_key(|command| format!("{command:?}"));
+        let mut expected = scene.commands().collect::<Vec<_>>();
+        expected.sort_by_key(|command| format!("{command:?}"));
+
+        assert_eq!(scene.len(), 2 * (8 + 4));
+        assert_eq!(all, expected);
+    }
+}
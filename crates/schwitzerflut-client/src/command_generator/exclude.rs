@@ -0,0 +1,333 @@
+use crate::command_generator::combinator::kept;
+use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+use schwitzerflut_protocol::command::Command;
+use schwitzerflut_protocol::coordinates::Coordinates;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Area of the canvas that must never be written into, in canvas coordinates.
+///
+/// Written as `<width>x<height>+<x>+<y>` for rectangles or as at least three `<x>,<y>` corners
+/// separated by `;` for polygons.
+#[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Region {
+    Rect {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Polygon(Vec<(u32, u32)>),
+}
+
+impl Region {
+    pub fn contains(&self, coordinates: &Coordinates) -> bool {
+        match self {
+            Self::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                (*x..x.saturating_add(*width)).contains(&coordinates.x)
+                    && (*y..y.saturating_add(*height)).contains(&coordinates.y)
+            }
+            Self::Polygon(corners) => polygon_contains(corners, coordinates),
+        }
+    }
+}
+
+/// Even-odd test of the center of the pixel
+fn polygon_contains(corners: &[(u32, u32)], coordinates: &Coordinates) -> bool {
+    let (x, y) = (coordinates.x as f64 + 0.5, coordinates.y as f64 + 0.5);
+    let mut inside = false;
+
+    for (index, &(x1, y1)) in corners.iter().enumerate() {
+        let (x2, y2) = corners[(index + 1) % corners.len()];
+        let (x1, y1, x2, y2) = (x1 as f64, y1 as f64, x2 as f64, y2 as f64);
+
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+impl FromStr for Region {
+    type Err = ParseRegionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(',') {
+            let corners = s
+                .split(';')
+                .map(|corner| {
+                    let (x, y) = corner
+                        .trim()
+                        .split_once(',')
+                        .ok_or(ParseRegionError::Syntax)?;
+                    Ok((x.trim().parse()?, y.trim().parse()?))
+                })
+                .collect::<Result<Vec<_>, ParseRegionError>>()?;
+
+            if corners.len() < 3 {
+                return Err(ParseRegionError::TooFewCorners);
+            }
+
+            return Ok(Self::Polygon(corners));
+        }
+
+        let (size, position) = s.split_once('+').ok_or(ParseRegionError::Syntax)?;
+        let (width, height) = size.split_once('x').ok_or(ParseRegionError::Syntax)?;
+        let (x, y) = position.split_once('+').ok_or(ParseRegionError::Syntax)?;
+
+        Ok(Self::Rect {
+            x: x.parse()?,
+            y: y.parse()?,
+            width: width.parse()?,
+            height: height.parse()?,
+        })
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = ParseRegionError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => write!(f, "{width}x{height}+{x}+{y}"),
+            Self::Polygon(corners) => {
+                let corners = corners
+                    .iter()
+                    .map(|(x, y)| format!("{x},{y}"))
+                    .collect::<Vec<_>>();
+                write!(f, "{}", corners.join(";"))
+            }
+        }
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ParseRegionError {
+    #[error("Expected '<width>x<height>+<x>+<y>' or '<x>,<y>;<x>,<y>;<x>,<y>...'")]
+    Syntax,
+
+    #[error("A polygon needs at least three corners")]
+    TooFewCorners,
+
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+}
+
+/// Regions read from a file, one per line. Empty lines and lines starting with `#` are ignored
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Exclusions(pub Vec<Region>);
+
+impl Exclusions {
+    pub fn contains(&self, coordinates: &Coordinates) -> bool {
+        self.0.iter().any(|region| region.contains(coordinates))
+    }
+}
+
+impl FromStr for Exclusions {
+    type Err = ParseExclusionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line, region)| {
+                region
+                    .parse()
+                    .map_err(|source| ParseExclusionsError { line, source })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+#[error("line {line}: {source}")]
+pub struct ParseExclusionsError {
+    pub line: usize,
+    pub source: ParseRegionError,
+}
+
+/// CommandGenerator wrapper that drops every pixel inside the excluded regions.
+///
+/// The remaining commands are looked up once when created, so they can still be accessed by index.
+pub struct Exclude<G>
+where
+    G: IndexedCommandGenerator,
+{
+    generator: G,
+    /// Indices of the commands of `generator` that are kept, `None` if all of them are
+    kept: Option<Vec<usize>>,
+}
+
+impl<G: IndexedCommandGenerator> Exclude<G> {
+    pub fn new(generator: G, exclusions: &Exclusions) -> Self {
+        let kept = (!exclusions.0.is_empty()).then(|| {
+            kept(&generator, |command| {
+                !exclusions.contains(&command.coordinates)
+            })
+        });
+
+        Self { generator, kept }
+    }
+}
+
+impl<G> CommandGenerator for Exclude<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (0..self.len()).map(|index| self.command_at(index))
+    }
+}
+
+impl<G> IndexedCommandGenerator for Exclude<G>
+where
+    G: IndexedCommandGenerator,
+{
+    fn len(&self) -> usize {
+        match &self.kept {
+            Some(kept) => kept.len(),
+            None => self.generator.len(),
+        }
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        match &self.kept {
+            Some(kept) => self.generator.command_at(kept[index]),
+            None => self.generator.command_at(index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::exclude::{
+        Exclude, Exclusions, ParseExclusionsError, ParseRegionError, Region,
+    };
+    use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+    use schwitzerflut_protocol::color::{Color, RgbColor};
+    use schwitzerflut_protocol::command::{Command, SetPixelCommand};
+    use schwitzerflut_protocol::coordinates::Coordinates;
+
+    struct Generator(Vec<Command>);
+
+    impl CommandGenerator for Generator {
+        fn commands(&self) -> impl Iterator<Item = Command> {
+            self.0.iter().cloned()
+        }
+    }
+
+    impl IndexedCommandGenerator for Generator {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn command_at(&self, index: usize) -> Command {
+            self.0[index].clone()
+        }
+    }
+
+    fn pixel(x: u32, y: u32) -> Command {
+        Command::SetPixel(SetPixelCommand::new(
+            Coordinates::new(x, y),
+            Color::Rgb(RgbColor::new(255, 255, 255)),
+        ))
+    }
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(
+            "30x20+5+10".parse(),
+            Ok(Region::Rect {
+                x: 5,
+                y: 10,
+                width: 30,
+                height: 20
+            })
+        );
+        assert_eq!(
+            "0,0; 10,0; 0,10".parse(),
+            Ok(Region::Polygon(vec![(0, 0), (10, 0), (0, 10)]))
+        );
+        assert_eq!(
+            "0,0;10,0".parse::<Region>(),
+            Err(ParseRegionError::TooFewCorners)
+        );
+        assert!("30x20".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_rect_contains() {
+        let region: Region = "2x2+1+1".parse().unwrap();
+
+        assert!(region.contains(&Coordinates::new(1, 1)));
+        assert!(region.contains(&Coordinates::new(2, 2)));
+        assert!(!region.contains(&Coordinates::new(3, 1)));
+        assert!(!region.contains(&Coordinates::new(0, 1)));
+    }
+
+    #[test]
+    fn test_polygon_contains() {
+        let triangle: Region = "0,0;10,0;0,10".parse().unwrap();
+
+        assert!(triangle.contains(&Coordinates::new(1, 1)));
+        assert!(triangle.contains(&Coordinates::new(8, 0)));
+        assert!(!triangle.contains(&Coordinates::new(8, 8)));
+        assert!(!triangle.contains(&Coordinates::new(10, 0)));
+    }
+
+    #[test]
+    fn test_parse_exclusions() {
+        let exclusions: Exclusions = "# friends\n\n4x4+0+0\n  1,1;5,1;5,5\n".parse().unwrap();
+        assert_eq!(exclusions.0.len(), 2);
+
+        assert_eq!(
+            "4x4+0+0\nnope".parse::<Exclusions>(),
+            Err(ParseExclusionsError {
+                line: 2,
+                source: ParseRegionError::Syntax
+            })
+        );
+    }
+
+    #[test]
+    fn test_exclude() {
+        let generator = Generator(vec![pixel(0, 0), pixel(1, 0), pixel(5, 5), pixel(2, 0)]);
+        let exclude = Exclude::new(
+            generator,
+            &Exclusions(vec![
+                "1x1+1+0".parse().unwrap(),
+                "4,4;6,4;6,6;4,6".parse().unwrap(),
+            ]),
+        );
+
+        assert_eq!(
+            exclude.commands().collect::<Vec<_>>(),
+            vec![pixel(0, 0), pixel(2, 0)]
+        );
+        assert_eq!(exclude.len(), 2);
+        assert_eq!(exclude.command_at(1), pixel(2, 0));
+    }
+}
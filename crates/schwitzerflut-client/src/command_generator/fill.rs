@@ -0,0 +1,90 @@
+use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+use schwitzerflut_protocol::color::Color;
+use schwitzerflut_protocol::command::{Command, SetPixelCommand};
+use schwitzerflut_protocol::coordinates::Coordinates;
+
+/// Rectangle of a single colour, drawn row by row
+#[derive(Clone, Debug)]
+pub struct Fill {
+    offset: Coordinates,
+    width: u32,
+    height: u32,
+    color: Color,
+}
+
+impl Fill {
+    pub fn new(offset: Coordinates, (width, height): (u32, u32), color: Color) -> Self {
+        Self {
+            offset,
+            width,
+            height,
+            color,
+        }
+    }
+}
+
+impl CommandGenerator for Fill {
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (0..self.len()).map(|index| self.command_at(index))
+    }
+}
+
+impl IndexedCommandGenerator for Fill {
+    fn len(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        assert!(index < self.len(), "index {index} out of bounds");
+        let (x, y) = (index % self.width as usize, index / self.width as usize);
+
+        Command::SetPixel(SetPixelCommand::new(
+            Coordinates::new(self.offset.x + x as u32, self.offset.y + y as u32),
+            self.color,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::fill::Fill;
+    use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+    use schwitzerflut_protocol::color::{Color, RgbColor};
+    use schwitzerflut_protocol::command::{Command, SetPixelCommand};
+    use schwitzerflut_protocol::coordinates::Coordinates;
+
+    #[test]
+    fn test_fill() {
+        let color = Color::Rgb(RgbColor::new(255, 0, 0));
+        let fill = Fill::new(Coordinates::new(10, 20), (3, 2), color);
+
+        let coordinates = fill
+            .commands()
+            .map(|command| match command {
+                Command::SetPixel(SetPixelCommand {
+                    coordinates,
+                    color: c,
+                }) if c == color => (coordinates.x, coordinates.y),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(fill.len(), 6);
+        assert_eq!(
+            coordinates,
+            vec![(10, 20), (11, 20), (12, 20), (10, 21), (11, 21), (12, 21)]
+        );
+    }
+
+    #[test]
+    fn test_empty_fill() {
+        let fill = Fill::new(
+            Coordinates::new(0, 0),
+            (0, 5),
+            Color::Rgb(RgbColor::new(0, 0, 0)),
+        );
+
+        assert!(fill.is_empty());
+        assert_eq!(fill.commands().count(), 0);
+    }
+}
@@ -1,49 +1,139 @@
-use crate::command_generator::CommandGenerator;
+use crate::command_generator::adjust::Adjustment;
+use crate::command_generator::alpha::{ChromaKey, Transparency};
+use crate::command_generator::importance::{copies, importance};
+use crate::command_generator::order::Order;
+use crate::command_generator::transform::{Axis, Crop, ResampleFilter, Transform};
+use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
 use image::imageops::FilterType;
-use image::{DynamicImage, ImageResult, RgbaImage};
-use schwitzerflut_protocol::color::{Color, RgbColor, RgbaColor};
+use image::{DynamicImage, GrayImage, ImageResult, Rgba, RgbaImage};
+use schwitzerflut_protocol::color::RgbColor;
 use schwitzerflut_protocol::command::{Command, SetPixelCommand};
 use schwitzerflut_protocol::coordinates::Coordinates;
+use std::cmp::Reverse;
 use std::iter;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-/// Pixel source
+/// Pixel source.
+///
+/// The pixels to draw are picked and ordered once when building, so commands can be looked up by
+/// index and clones share all buffers.
 #[derive(Clone)]
 pub struct ImageSource {
-    image: RgbaImage,
+    image: Arc<RgbaImage>,
     offset: Coordinates,
-    include_transparent_pixels: bool,
+    transparency: Transparency,
+    /// Positions of the pixels to draw, in drawing order
+    pixels: Arc<[(u32, u32)]>,
+}
+
+impl ImageSource {
+    /// The image commands are generated from, without the offset applied
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
 }
 
 impl CommandGenerator for ImageSource {
     fn commands(&self) -> impl Iterator<Item = Command> {
-        self.image
-            .enumerate_pixels()
-            .filter(|(_, _, color)| self.include_transparent_pixels || color.0[3] != 0)
-            .map(|(x, y, color)| {
-                Command::SetPixel(SetPixelCommand {
-                    coordinates: Coordinates {
-                        x: x + self.offset.x,
-                        y: y + self.offset.y,
-                    },
-                    color: Color::Rgba(RgbaColor {
-                        rgb: RgbColor {
-                            r: color[0],
-                            g: color[1],
-                            b: color[2],
-                        },
-                        alpha: color[3],
-                    }),
-                })
+        (0..self.len()).map(|index| self.command_at(index))
+    }
+}
+
+impl IndexedCommandGenerator for ImageSource {
+    fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        let (x, y) = self.pixels[index];
+
+        Command::SetPixel(SetPixelCommand {
+            coordinates: Coordinates {
+                x: x + self.offset.x,
+                y: y + self.offset.y,
+            },
+            color: self.transparency.color(self.image.get_pixel(x, y)),
+        })
+    }
+}
+
+/// Picks the pixels to draw and puts them in drawing order.
+///
+/// Pixels are ordered by `order` first, then by the weight in `mask`, keeping the order among
+/// pixels of the same weight. Pixels on edges are repeated in extra passes at the end.
+fn arrange(
+    image: &RgbaImage,
+    mask: Option<&GrayImage>,
+    include: impl Fn(&Rgba<u8>) -> bool,
+    order: Order,
+    seed: u64,
+    importance_repeats: u32,
+) -> Vec<(u32, u32)> {
+    // priority of a pixel, pixels with a weight of 0 are not drawn
+    let weight = |x: u32, y: u32| mask.map_or(u8::MAX, |mask| mask.get_pixel(x, y)[0]);
+
+    let mut pixels = image
+        .enumerate_pixels()
+        .filter(|(_, _, color)| include(color))
+        .filter(|&(x, y, _)| weight(x, y) > 0)
+        .map(|(x, y, _)| (x, y))
+        .collect::<Vec<_>>();
+
+    // shared by the edge order and the repeats, as it is the slowest part
+    let importance = (order == Order::Edges || importance_repeats > 1).then(|| importance(image));
+
+    order.arrange(
+        &mut pixels,
+        image,
+        importance.as_deref(),
+        seed,
+        |&position| position,
+    );
+
+    if mask.is_some() {
+        // stable, so pixels of the same weight stay in order
+        pixels.sort_by_key(|&(x, y)| Reverse(weight(x, y)));
+    }
+
+    if let Some(importance) = importance.filter(|_| importance_repeats > 1) {
+        let width = image.width();
+        let importance = pixels
+            .iter()
+            .map(|&(x, y)| importance[(y * width + x) as usize])
+            .collect::<Vec<_>>();
+        let copies = copies(&importance, importance_repeats);
+
+        // every extra pass repeats the pixels that still need copies, in the same order
+        pixels = (1..=importance_repeats)
+            .flat_map(|pass| {
+                pixels
+                    .iter()
+                    .zip(&copies)
+                    .filter(move |(_, &copies)| copies >= pass)
+                    .map(|(&pixel, _)| pixel)
             })
+            .collect();
     }
+
+    pixels
 }
 
+/// Builds an [`ImageSource`], applying transforms and then colour adjustments in the order they
+/// were added
 pub struct ImageSourceBuilder {
     image: DynamicImage,
     offset: Option<Coordinates>,
-    resize: Option<(u32, u32)>,
+    transforms: Vec<Transform>,
+    adjustments: Vec<Adjustment>,
+    filter: ResampleFilter,
     include_transparent: bool,
+    transparency: Transparency,
+    chroma_key: Option<ChromaKey>,
+    mask: Option<DynamicImage>,
+    order: Order,
+    seed: u64,
+    importance_repeats: u32,
 }
 
 impl ImageSourceBuilder {
@@ -51,8 +141,16 @@ impl ImageSourceBuilder {
         Self {
             image,
             offset: None,
-            resize: None,
+            transforms: Vec::new(),
+            adjustments: Vec::new(),
+            filter: ResampleFilter::default(),
             include_transparent: false,
+            transparency: Transparency::default(),
+            chroma_key: None,
+            mask: None,
+            order: Order::default(),
+            seed: 0,
+            importance_repeats: 1,
         }
     }
 
@@ -60,8 +158,49 @@ impl ImageSourceBuilder {
         Ok(Self::new(image::open(path)?))
     }
 
-    pub fn resize(mut self, size: (u32, u32)) -> Self {
-        self.resize = Some(size);
+    /// Appends a step to the transform pipeline.
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    /// Scales to exactly the given size, ignoring the aspect ratio.
+    pub fn resize(self, (width, height): (u32, u32)) -> Self {
+        self.transform(Transform::Resize { width, height })
+    }
+
+    /// Scales into the given bounds keeping the aspect ratio. A missing bound is unlimited.
+    pub fn fit(self, width: Option<u32>, height: Option<u32>) -> Self {
+        self.transform(Transform::Fit { width, height })
+    }
+
+    pub fn crop(self, crop: Crop) -> Self {
+        self.transform(Transform::Crop(crop))
+    }
+
+    /// Rotates clockwise by the given angle in degrees.
+    pub fn rotate(self, degrees: f32) -> Self {
+        self.transform(Transform::Rotate(degrees))
+    }
+
+    pub fn flip(self, axis: Axis) -> Self {
+        self.transform(Transform::Flip(axis))
+    }
+
+    /// Repeats every pixel `factor` times in both directions, keeping hard edges.
+    pub fn upscale(self, factor: u32) -> Self {
+        self.transform(Transform::Upscale(factor))
+    }
+
+    /// Appends a colour adjustment, applied after all transforms.
+    pub fn adjust(mut self, adjustment: Adjustment) -> Self {
+        self.adjustments.push(adjustment);
+        self
+    }
+
+    /// Filter used by every scaling and rotating step.
+    pub fn filter(mut self, filter: ResampleFilter) -> Self {
+        self.filter = filter;
         self
     }
 
@@ -73,31 +212,108 @@ impl ImageSourceBuilder {
         self
     }
 
+    /// Pixels with a lower alpha are transparent. Defaults to 1, so only fully transparent
+    /// pixels are.
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.transparency.threshold = threshold;
+        self
+    }
+
+    /// Blends translucent pixels onto the given colour and sends them as plain RGB.
+    pub fn background(mut self, background: RgbColor) -> Self {
+        self.transparency.background = Some(background);
+        self
+    }
+
+    /// Treats pixels within `tolerance` of the given colour in every channel as transparent.
+    pub fn chroma_key(mut self, color: RgbColor, tolerance: u8) -> Self {
+        self.chroma_key = Some(ChromaKey { color, tolerance });
+        self
+    }
+
+    /// Only draws pixels where the mask is not black, brighter pixels first.
+    ///
+    /// The mask is scaled to the size of the image after all transforms.
+    pub fn mask(mut self, mask: DynamicImage) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Order the pixels are drawn in. Pixels of the same mask weight keep this order.
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Seed of orders that are random, the same seed always gives the same order.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sends the pixels on the strongest edges up to `max` times per pass, the others
+    /// proportionally less often, but at least once.
+    pub fn importance_repeats(mut self, max: u32) -> Self {
+        self.importance_repeats = max;
+        self
+    }
+
     pub fn offset(mut self, offset: Coordinates) -> Self {
         self.offset = Some(offset);
         self
     }
 
     pub fn build(self) -> ImageSource {
-        let mut image = self.image;
+        let mut image = self
+            .transforms
+            .iter()
+            .fold(self.image, |image, transform| {
+                transform.apply(image, self.filter)
+            })
+            .to_rgba8();
 
-        if let Some((width, height)) = self.resize {
-            image.resize(width, height, FilterType::CatmullRom);
+        // keyed on the colours of the image, before they are adjusted
+        if let Some(chroma_key) = self.chroma_key {
+            chroma_key.apply(&mut image);
         }
 
+        for adjustment in &self.adjustments {
+            adjustment.apply(&mut image);
+        }
+
+        let mask = self.mask.map(|mask| {
+            let (width, height) = image.dimensions();
+            mask.resize_exact(width, height, FilterType::Nearest)
+                .to_luma8()
+        });
+
+        let transparency = self.transparency;
+        let pixels = arrange(
+            &image,
+            mask.as_ref(),
+            |color| self.include_transparent || !transparency.is_transparent(color),
+            self.order,
+            self.seed,
+            self.importance_repeats,
+        );
+
         ImageSource {
-            image: image.to_rgba8(),
+            image: Arc::new(image),
             offset: self.offset.unwrap_or(Coordinates::new(0, 0)),
-            include_transparent_pixels: self.include_transparent,
+            transparency,
+            pixels: pixels.into(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::command_generator::adjust::Adjustment;
     use crate::command_generator::image::{ImageSource, ImageSourceBuilder};
-    use crate::command_generator::CommandGenerator;
-    use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
+    use crate::command_generator::order::Order;
+    use crate::command_generator::transform::ResampleFilter;
+    use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+    use image::{DynamicImage, GenericImage, GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
     use schwitzerflut_protocol::color::{Color, RgbColor, RgbaColor};
     use schwitzerflut_protocol::command::{Command, SetPixelCommand};
     use schwitzerflut_protocol::coordinates::Coordinates;
@@ -329,4 +545,162 @@ mod tests {
 
         assert_eq!(expected, commands);
     }
+
+    #[test]
+    pub fn test_image_with_transforms() {
+        let source = ImageSourceBuilder::new(get_test_image())
+            .include_transparent_pixels(true)
+            .crop("4x2+0+0".parse().unwrap())
+            .rotate(90.0)
+            .resize((4, 8))
+            .filter(ResampleFilter::Nearest)
+            .build();
+
+        assert_eq!(source.image().dimensions(), (4, 8));
+        assert_eq!(source.commands().count(), 32);
+        assert_eq!(source.image().get_pixel(0, 0), &Rgba([0, 255, 0, 1]));
+    }
+
+    #[test]
+    pub fn test_image_with_adjustments() {
+        let source = ImageSourceBuilder::new(get_test_image())
+            .adjust(Adjustment::Invert)
+            .adjust(Adjustment::Brightness(0.5))
+            .build();
+
+        assert_eq!(source.image().get_pixel(0, 0), &Rgba([0, 128, 128, 1]));
+    }
+
+    #[test]
+    pub fn test_image_with_background() {
+        let source = ImageSourceBuilder::new(get_test_image())
+            .chroma_key(RgbColor::new(0, 255, 0), 0)
+            .background(RgbColor::new(0, 0, 0))
+            .build();
+
+        let commands = source.commands().collect::<Vec<_>>();
+
+        // the green row is keyed out and the white row is transparent
+        assert_eq!(commands.len(), 8);
+        assert_eq!(
+            commands[0],
+            Command::SetPixel(SetPixelCommand::new(
+                Coordinates::new(0, 0),
+                Color::Rgb(RgbColor::new(1, 0, 0))
+            ))
+        );
+    }
+
+    #[test]
+    pub fn test_image_with_mask() {
+        let mut mask = GrayImage::new(2, 2);
+        mask.put_pixel(1, 0, Luma([255]));
+        mask.put_pixel(0, 1, Luma([128]));
+
+        let source = ImageSourceBuilder::new(get_test_image())
+            .include_transparent_pixels(true)
+            .mask(DynamicImage::ImageLuma8(mask))
+            .build();
+
+        let coordinates = source
+            .commands()
+            .map(|command| match command {
+                Command::SetPixel(command) => (command.coordinates.x, command.coordinates.y),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        // the brighter top right quarter comes before the bottom left one
+        assert_eq!(
+            coordinates,
+            vec![
+                (2, 0),
+                (3, 0),
+                (2, 1),
+                (3, 1),
+                (0, 2),
+                (1, 2),
+                (0, 3),
+                (1, 3)
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_order_within_mask_weight() {
+        let mut mask = GrayImage::new(2, 2);
+        mask.put_pixel(1, 0, Luma([255]));
+        mask.put_pixel(0, 1, Luma([128]));
+
+        let source = ImageSourceBuilder::new(get_test_image())
+            .include_transparent_pixels(true)
+            .mask(DynamicImage::ImageLuma8(mask))
+            .order(Order::Checkerboard)
+            .build();
+
+        let coordinates = source
+            .commands()
+            .map(|command| match command {
+                Command::SetPixel(command) => (command.coordinates.x, command.coordinates.y),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            coordinates,
+            vec![
+                (2, 0),
+                (3, 1),
+                (3, 0),
+                (2, 1),
+                (0, 2),
+                (1, 3),
+                (1, 2),
+                (0, 3)
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_importance_repeats() {
+        let mut image = RgbaImage::from_pixel(5, 5, Rgba([0, 0, 0, 255]));
+        image.put_pixel(2, 2, Rgba([255, 255, 255, 255]));
+
+        let coordinates = ImageSourceBuilder::new(DynamicImage::ImageRgba8(image))
+            .importance_repeats(3)
+            .build()
+            .commands()
+            .map(|command| match command {
+                Command::SetPixel(command) => (command.coordinates.x, command.coordinates.y),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        // every pixel once, then two more passes over the dot and its neighbours
+        assert_eq!(coordinates.len(), 25 + 9 + 9);
+        assert_eq!(coordinates[12], (2, 2));
+        assert!(coordinates[25..34].contains(&(2, 2)));
+        assert_eq!(coordinates[25..34], coordinates[34..]);
+        assert!(!coordinates[25..].contains(&(0, 0)));
+    }
+
+    #[test]
+    pub fn test_command_at_matches_commands() {
+        let mut image = RgbaImage::from_pixel(4, 3, Rgba([10, 20, 30, 255]));
+        image.put_pixel(2, 1, Rgba([0, 0, 0, 0]));
+
+        let source = ImageSourceBuilder::new(DynamicImage::ImageRgba8(image))
+            .offset(Coordinates::new(5, 5))
+            .order(Order::Shuffle)
+            .build();
+        let commands = source.commands().collect::<Vec<_>>();
+
+        assert_eq!(source.len(), 11);
+        assert_eq!(
+            commands,
+            (0..source.len())
+                .map(|index| source.command_at(index))
+                .collect::<Vec<_>>()
+        );
+    }
 }
@@ -0,0 +1,104 @@
+use image::RgbaImage;
+
+/// How recognisable every pixel is, in row-major order.
+///
+/// Local contrast: the largest difference in luma with alpha applied to any of the 8 neighbours.
+/// A lone dot or a thin line scores as high as its surroundings, and outlines against transparent
+/// surroundings count as well. Flat areas are 0.
+pub fn importance(image: &RgbaImage) -> Vec<u32> {
+    let (width, height) = image.dimensions();
+    let luma = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0.map(|channel| channel as i32);
+            (2126 * r + 7152 * g + 722 * b) / 10000 * a / 255
+        })
+        .collect::<Vec<_>>();
+
+    // neighbours beyond the border are left out, so it doesn't look like an edge
+    let at = |x: i64, y: i64| {
+        let inside = (0..width as i64).contains(&x) && (0..height as i64).contains(&y);
+        inside.then(|| luma[(y as u32 * width + x as u32) as usize])
+    };
+
+    (0..height as i64)
+        .flat_map(|y| (0..width as i64).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let center = luma[(y as u32 * width + x as u32) as usize];
+
+            (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .filter_map(|(dx, dy)| at(x + dx, y + dy))
+                .map(|neighbour| neighbour.abs_diff(center))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// How often to send every pixel, between 1 and `max`, in proportion to its importance
+pub fn copies(importance: &[u32], max: u32) -> Vec<u32> {
+    let highest = importance.iter().copied().max().unwrap_or(0).max(1) as u64;
+
+    importance
+        .iter()
+        .map(|&importance| {
+            let extra = (max.max(1) - 1) as u64 * importance as u64;
+            1 + ((extra + highest / 2) / highest) as u32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::importance::{copies, importance};
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_flat_image_has_no_edges() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([200, 10, 10, 255]));
+
+        assert!(importance(&image).iter().all(|&importance| importance == 0));
+    }
+
+    #[test]
+    fn test_edges_are_important() {
+        // black left half, white right half
+        let image = RgbaImage::from_fn(6, 3, |x, _| match x < 3 {
+            true => Rgba([0, 0, 0, 255]),
+            false => Rgba([255, 255, 255, 255]),
+        });
+        let importance = importance(&image);
+        let row = &importance[6..12];
+
+        assert_eq!(row[0], 0);
+        assert!(row[2] > 0 && row[3] > 0);
+        assert_eq!(row[5], 0);
+    }
+
+    #[test]
+    fn test_transparent_surroundings_are_edges() {
+        let mut image = RgbaImage::from_pixel(3, 3, Rgba([255, 255, 255, 0]));
+        image.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+
+        assert!(importance(&image)[0] > 0);
+    }
+
+    #[test]
+    fn test_dots_are_as_important_as_their_surroundings() {
+        let mut image = RgbaImage::from_pixel(5, 5, Rgba([0, 0, 0, 255]));
+        image.put_pixel(2, 2, Rgba([255, 255, 255, 255]));
+        let importance = importance(&image);
+
+        assert_eq!(importance[2 * 5 + 2], 255);
+        assert_eq!(importance[5 + 1], 255);
+        assert_eq!(importance[0], 0);
+    }
+
+    #[test]
+    fn test_copies() {
+        assert_eq!(copies(&[0, 50, 100], 3), vec![1, 2, 3]);
+        assert_eq!(copies(&[0, 50, 100], 1), vec![1, 1, 1]);
+        assert_eq!(copies(&[0, 0], 4), vec![1, 1]);
+    }
+}
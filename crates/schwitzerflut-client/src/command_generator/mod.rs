@@ -1,7 +1,130 @@
 use schwitzerflut_protocol::command::Command;
+use std::sync::Arc;
 
+pub mod adjust;
+pub mod alpha;
+pub mod animation;
+pub mod combinator;
+pub mod exclude;
+pub mod fill;
 pub mod image;
+pub mod importance;
+pub mod order;
+pub mod registry;
 pub mod shard;
+pub mod text;
+pub mod transform;
+
 pub trait CommandGenerator {
     fn commands(&self) -> impl Iterator<Item = Command>;
 }
+
+/// CommandGenerator whose commands can be looked up by their index, without generating the ones
+/// before them
+pub trait IndexedCommandGenerator: CommandGenerator {
+    /// Number of commands generated
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The command at `index` of [`commands`](CommandGenerator::commands).
+    ///
+    /// Panics if `index` is out of bounds.
+    fn command_at(&self, index: usize) -> Command;
+}
+
+/// Object safe companion of [`IndexedCommandGenerator`], so generators can be boxed, stored
+/// together and picked at runtime.
+///
+/// Implemented for every indexed generator. `dyn DynCommandGenerator` implements the regular traits
+/// in turn, so boxed generators can be wrapped like any other.
+pub trait DynCommandGenerator: Send + Sync {
+    fn dyn_commands(&self) -> Box<dyn Iterator<Item = Command> + '_>;
+
+    fn dyn_len(&self) -> usize;
+
+    fn dyn_command_at(&self, index: usize) -> Command;
+}
+
+impl<G> DynCommandGenerator for G
+where
+    G: IndexedCommandGenerator + Send + Sync,
+{
+    fn dyn_commands(&self) -> Box<dyn Iterator<Item = Command> + '_> {
+        Box::new(self.commands())
+    }
+
+    fn dyn_len(&self) -> usize {
+        self.len()
+    }
+
+    fn dyn_command_at(&self, index: usize) -> Command {
+        self.command_at(index)
+    }
+}
+
+impl CommandGenerator for dyn DynCommandGenerator + '_ {
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        self.dyn_commands()
+    }
+}
+
+impl IndexedCommandGenerator for dyn DynCommandGenerator + '_ {
+    fn len(&self) -> usize {
+        self.dyn_len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        self.dyn_command_at(index)
+    }
+}
+
+impl<G: CommandGenerator + ?Sized> CommandGenerator for &G {
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (**self).commands()
+    }
+}
+
+impl<G: IndexedCommandGenerator + ?Sized> IndexedCommandGenerator for &G {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        (**self).command_at(index)
+    }
+}
+
+impl<G: CommandGenerator + ?Sized> CommandGenerator for Box<G> {
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (**self).commands()
+    }
+}
+
+impl<G: IndexedCommandGenerator + ?Sized> IndexedCommandGenerator for Box<G> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        (**self).command_at(index)
+    }
+}
+
+impl<G: CommandGenerator + ?Sized> CommandGenerator for Arc<G> {
+    fn commands(&self) -> impl Iterator<Item = Command> {
+        (**self).commands()
+    }
+}
+
+impl<G: IndexedCommandGenerator + ?Sized> IndexedCommandGenerator for Arc<G> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn command_at(&self, index: usize) -> Command {
+        (**self).command_at(index)
+    }
+}
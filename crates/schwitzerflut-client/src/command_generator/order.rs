@@ -0,0 +1,293 @@
+use crate::command_generator::importance::importance;
+use clap::ValueEnum;
+use image::RgbaImage;
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// Order in which the pixels of an image are drawn
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Order {
+    /// Left to right, top to bottom
+    #[default]
+    RowMajor,
+    /// Random order, determined by the seed
+    Shuffle,
+    /// Along a Hilbert curve, so every stretch of commands covers a compact area
+    Hilbert,
+    /// Along a Z-order curve
+    #[value(name = "z-order")]
+    #[serde(rename = "z-order")]
+    Morton,
+    /// Outwards from the centre
+    Spiral,
+    /// Every 8th row first, then the rows in between, like an interlaced PNG
+    Interlaced,
+    /// Every other pixel first, then the ones in between
+    Checkerboard,
+    /// Strongest edges first and flat areas last, so the image is recognisable early
+    Edges,
+}
+
+impl Order {
+    /// Sorts `items` of an image into this order, given their position in it.
+    ///
+    /// Expects `items` in row-major order. The result only depends on the items, the image and
+    /// the seed. `importance` is the [`importance`] of `image` if the caller already has it, it is
+    /// computed when needed otherwise.
+    pub fn arrange<T>(
+        &self,
+        items: &mut [T],
+        image: &RgbaImage,
+        importance: Option<&[u32]>,
+        seed: u64,
+        position: impl Fn(&T) -> (u32, u32),
+    ) {
+        let (width, height) = image.dimensions();
+
+        match self {
+            Self::RowMajor => {}
+            Self::Shuffle => shuffle(items, seed),
+            Self::Hilbert => {
+                let side = width.max(height).next_power_of_two();
+                items.sort_by_cached_key(|item| {
+                    let (x, y) = position(item);
+                    hilbert(side, x, y)
+                });
+            }
+            Self::Morton => items.sort_by_cached_key(|item| {
+                let (x, y) = position(item);
+                z_order(x, y)
+            }),
+            Self::Spiral => {
+                // doubled, so the centre of even sized images lies on whole numbers
+                let (center_x, center_y) = (width as i64 - 1, height as i64 - 1);
+                items.sort_by_cached_key(|item| {
+                    let (x, y) = position(item);
+                    let (dx, dy) = (2 * x as i64 - center_x, 2 * y as i64 - center_y);
+                    let ring = dx.abs().max(dy.abs());
+                    (ring, ring_position(ring, dx, dy))
+                });
+            }
+            Self::Interlaced => items.sort_by_cached_key(|item| {
+                let (x, y) = position(item);
+                (interlace_pass(y), y, x)
+            }),
+            Self::Checkerboard => items.sort_by_cached_key(|item| {
+                let (x, y) = position(item);
+                ((x + y) % 2, y, x)
+            }),
+            Self::Edges => {
+                let computed;
+                let importance = match importance {
+                    Some(importance) => importance,
+                    None => {
+                        computed = self::importance(image);
+                        &computed
+                    }
+                };
+                items.sort_by_cached_key(|item| {
+                    let (x, y) = position(item);
+                    Reverse(importance[(y * width + x) as usize])
+                });
+            }
+        }
+    }
+}
+
+/// Fisher-Yates with a SplitMix64 generator, so the order never changes for a seed
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    for index in (1..items.len()).rev() {
+        let other = (next() % (index as u64 + 1)) as usize;
+        items.swap(index, other);
+    }
+}
+
+/// Distance along a Hilbert curve covering a `side` by `side` square, `side` a power of two
+fn hilbert(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut distance = 0;
+    let mut step = side / 2;
+
+    while step > 0 {
+        let rx = (x & step > 0) as u32;
+        let ry = (y & step > 0) as u32;
+        distance += step as u64 * step as u64 * ((3 * rx) ^ ry) as u64;
+
+        // rotate the quadrant, so the curve stays connected
+        if ry == 0 {
+            if rx == 1 {
+                x = step - 1 - (x & (step - 1));
+                y = step - 1 - (y & (step - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        step /= 2;
+    }
+
+    distance
+}
+
+/// Interleaves the bits of both coordinates
+fn z_order(x: u32, y: u32) -> u64 {
+    let spread = |value: u32| {
+        let mut value = value as u64;
+        value = (value | (value << 16)) & 0x0000_ffff_0000_ffff;
+        value = (value | (value << 8)) & 0x00ff_00ff_00ff_00ff;
+        value = (value | (value << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+        (value | (value << 1)) & 0x5555_5555_5555_5555
+    };
+
+    spread(x) | (spread(y) << 1)
+}
+
+/// Position on the square ring at distance `ring` around the centre, clockwise from the top left
+fn ring_position(ring: i64, dx: i64, dy: i64) -> i64 {
+    if dy == -ring {
+        dx + ring
+    } else if dx == ring {
+        2 * ring + dy + ring
+    } else if dy == ring {
+        4 * ring + ring - dx
+    } else {
+        6 * ring + ring - dy
+    }
+}
+
+/// Row passes of Adam7
+fn interlace_pass(y: u32) -> u32 {
+    match y % 8 {
+        0 => 0,
+        4 => 1,
+        2 | 6 => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::order::Order;
+    use image::{Rgba, RgbaImage};
+
+    fn arrange(order: Order, width: u32, height: u32, seed: u64) -> Vec<(u32, u32)> {
+        let image = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+        arrange_image(order, &image, seed)
+    }
+
+    fn arrange_image(order: Order, image: &RgbaImage, seed: u64) -> Vec<(u32, u32)> {
+        let mut pixels = (0..image.height())
+            .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+            .collect::<Vec<_>>();
+        order.arrange(&mut pixels, image, None, seed, |&position| position);
+
+        pixels
+    }
+
+    #[test]
+    fn test_every_order_keeps_all_pixels() {
+        for order in [
+            Order::RowMajor,
+            Order::Shuffle,
+            Order::Hilbert,
+            Order::Morton,
+            Order::Spiral,
+            Order::Interlaced,
+            Order::Checkerboard,
+            Order::Edges,
+        ] {
+            let mut pixels = arrange(order, 7, 5, 1);
+            pixels.sort_by_key(|&(x, y)| (y, x));
+
+            assert_eq!(pixels, arrange(Order::RowMajor, 7, 5, 1), "{order:?}");
+        }
+    }
+
+    #[test]
+    fn test_shuffle_is_reproducible() {
+        let shuffled = arrange(Order::Shuffle, 8, 8, 42);
+
+        assert_eq!(shuffled, arrange(Order::Shuffle, 8, 8, 42));
+        assert_ne!(shuffled, arrange(Order::Shuffle, 8, 8, 43));
+        assert_ne!(shuffled, arrange(Order::RowMajor, 8, 8, 42));
+    }
+
+    #[test]
+    fn test_hilbert_only_takes_single_steps() {
+        let pixels = arrange(Order::Hilbert, 8, 8, 0);
+
+        assert_eq!(pixels[0], (0, 0));
+        for pair in pixels.windows(2) {
+            let [(x1, y1), (x2, y2)] = [pair[0], pair[1]];
+            assert_eq!(x1.abs_diff(x2) + y1.abs_diff(y2), 1);
+        }
+    }
+
+    #[test]
+    fn test_z_order() {
+        assert_eq!(
+            arrange(Order::Morton, 4, 2, 0),
+            vec![
+                (0, 0),
+                (1, 0),
+                (0, 1),
+                (1, 1),
+                (2, 0),
+                (3, 0),
+                (2, 1),
+                (3, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spiral_starts_in_the_centre() {
+        let pixels = arrange(Order::Spiral, 5, 5, 0);
+
+        assert_eq!(pixels[0], (2, 2));
+        assert_eq!(&pixels[1..3], &[(1, 1), (2, 1)]);
+        assert!(pixels[1..9]
+            .iter()
+            .all(|&(x, y)| x.abs_diff(2) <= 1 && y.abs_diff(2) <= 1));
+    }
+
+    #[test]
+    fn test_interlaced() {
+        let rows = arrange(Order::Interlaced, 1, 9, 0)
+            .into_iter()
+            .map(|(_, y)| y)
+            .collect::<Vec<_>>();
+
+        assert_eq!(rows, vec![0, 8, 4, 2, 6, 1, 3, 5, 7]);
+    }
+
+    #[test]
+    fn test_checkerboard() {
+        assert_eq!(
+            arrange(Order::Checkerboard, 2, 2, 0),
+            vec![(0, 0), (1, 1), (1, 0), (0, 1)]
+        );
+    }
+
+    #[test]
+    fn test_edges_first() {
+        let mut image = RgbaImage::from_pixel(5, 5, Rgba([0, 0, 0, 255]));
+        image.put_pixel(4, 4, Rgba([255, 255, 255, 255]));
+
+        let pixels = arrange_image(Order::Edges, &image, 0);
+
+        // the bright corner and its neighbours come first, the flat rest keeps row-major order
+        assert!(pixels[..4].contains(&(4, 4)));
+        assert!(pixels[..4].contains(&(3, 3)));
+        assert_eq!(pixels[4..6], [(0, 0), (1, 0)]);
+    }
+}
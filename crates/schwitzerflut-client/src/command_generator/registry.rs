@@ -0,0 +1,118 @@
+use crate::command_generator::DynCommandGenerator;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Creates a generator from what it is configured with, e.g. a job
+pub type Constructor<C> = fn(&C) -> anyhow::Result<Box<dyn DynCommandGenerator>>;
+
+/// Generators that can be picked by name at runtime, e.g. from the command line or a job file
+pub struct Registry<C> {
+    constructors: BTreeMap<&'static str, Constructor<C>>,
+}
+
+impl<C> Registry<C> {
+    pub fn new() -> Self {
+        Self {
+            constructors: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a generator, replacing any previous one of the same name
+    pub fn register(mut self, name: &'static str, constructor: Constructor<C>) -> Self {
+        self.constructors.insert(name, constructor);
+        self
+    }
+
+    /// Names of all generators, sorted
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.constructors.keys().copied()
+    }
+
+    /// Whether a generator of that name exists
+    pub fn check(&self, name: &str) -> Result<(), UnknownGeneratorError> {
+        match self.constructors.contains_key(name) {
+            true => Ok(()),
+            false => Err(UnknownGeneratorError {
+                name: name.to_string(),
+                known: self.names().collect::<Vec<_>>().join(", "),
+            }),
+        }
+    }
+
+    pub fn create(&self, name: &str, context: &C) -> anyhow::Result<Box<dyn DynCommandGenerator>> {
+        self.check(name)?;
+
+        self.constructors[name](context)
+    }
+}
+
+impl<C> Default for Registry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+#[error("unknown generator '{name}', expected one of {known}")]
+pub struct UnknownGeneratorError {
+    pub name: String,
+    pub known: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::fill::Fill;
+    use crate::command_generator::registry::{Registry, UnknownGeneratorError};
+    use crate::command_generator::{CommandGenerator, DynCommandGenerator};
+    use schwitzerflut_protocol::color::{Color, RgbColor};
+    use schwitzerflut_protocol::coordinates::Coordinates;
+
+    fn fill(size: &u32) -> anyhow::Result<Box<dyn DynCommandGenerator>> {
+        Ok(Box::new(Fill::new(
+            Coordinates::new(0, 0),
+            (*size, *size),
+            Color::Rgb(RgbColor::new(0, 0, 0)),
+        )))
+    }
+
+    fn nothing(_: &u32) -> anyhow::Result<Box<dyn DynCommandGenerator>> {
+        anyhow::bail!("nothing to draw")
+    }
+
+    #[test]
+    fn test_create_by_name() {
+        let registry = Registry::new()
+            .register("fill", fill)
+            .register("nothing", nothing);
+
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec!["fill", "nothing"]
+        );
+        assert_eq!(registry.create("fill", &3).unwrap().commands().count(), 9);
+        assert!(registry.create("nothing", &3).is_err());
+
+        let error = registry.create("text", &3).err().unwrap();
+        assert_eq!(
+            error.downcast_ref(),
+            Some(&UnknownGeneratorError {
+                name: "text".to_string(),
+                known: "fill, nothing".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_boxed_generators() {
+        let generators: Vec<Box<dyn DynCommandGenerator>> =
+            vec![fill(&1).unwrap(), fill(&2).unwrap()];
+
+        assert_eq!(
+            generators
+                .iter()
+                .map(|generator| generator.commands().count())
+                .collect::<Vec<_>>(),
+            vec![1, 4]
+        );
+    }
+}
@@ -1,7 +1,25 @@
-use crate::command_generator::CommandGenerator;
+use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
+use clap::ValueEnum;
 use schwitzerflut_protocol::command::Command;
+use serde::Deserialize;
+use std::sync::Arc;
 
-/// CommandGenerator wrapper that implements simple modulus sharding
+/// How the commands of a generator are split between shards
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShardStrategy {
+    /// Every `num_shards`th command, so every shard covers the whole image
+    #[default]
+    Modulus,
+    /// Consecutive runs of commands in drawing order
+    Chunk,
+    /// Rectangular tiles, so the writes of every shard stay close together
+    Tiles,
+    /// Horizontal bands of rows
+    Rows,
+}
+
+/// CommandGenerator wrapper that only keeps the commands of one shard
 pub struct Shard<G>
 where
     G: CommandGenerator,
@@ -9,6 +27,9 @@ where
     generator: G,
     shard: usize,
     num_shards: usize,
+    strategy: ShardStrategy,
+    weights: Vec<u32>,
+    layout: Option<Arc<Layout>>,
 }
 
 impl<G: CommandGenerator> Shard<G> {
@@ -17,30 +38,255 @@ impl<G: CommandGenerator> Shard<G> {
             generator,
             shard,
             num_shards,
+            strategy: ShardStrategy::default(),
+            weights: Vec::new(),
+            layout: None,
         }
     }
+
+    pub fn strategy(mut self, strategy: ShardStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Relative share of every shard, e.g. `[4, 1, 1]` gives the first shard two thirds of the
+    /// pixels. Without weights, or if they are all 0, every shard gets the same share.
+    pub fn weights(mut self, weights: Vec<u32>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    /// Layout of the same generator shared by all of its shards, so it is only computed once.
+    /// Replaces the number of shards, the strategy and the weights.
+    pub fn layout(mut self, layout: Arc<Layout>) -> Self {
+        self.layout = Some(layout);
+        self
+    }
 }
 
 impl<G> CommandGenerator for Shard<G>
 where
-    G: CommandGenerator,
+    G: IndexedCommandGenerator,
 {
+    /// Only generates the commands of this shard. Without a shared layout, tiles and rows still
+    /// have to look at the position of every command.
     fn commands(&self) -> impl Iterator<Item = Command> {
-        self.generator
-            .commands()
-            .enumerate()
-            .filter(|(index, _)| index % self.num_shards == self.shard)
-            .map(|(_, command)| command)
+        let layout = self.layout.clone().unwrap_or_else(|| {
+            Arc::new(Layout::new(
+                self.strategy,
+                &self.generator,
+                self.num_shards,
+                &self.weights,
+            ))
+        });
+
+        layout
+            .indices(self.shard)
+            .into_iter()
+            .map(|index| self.generator.command_at(index))
     }
 }
 
+/// Which commands of a generator belong to which shard
+pub struct Layout {
+    strategy: ShardStrategy,
+    len: usize,
+    /// One per shard, at least one of them not 0
+    weights: Vec<u64>,
+    /// Indices of every shard, for the strategies that have to look at all commands to find them
+    assigned: Option<Vec<Vec<usize>>>,
+}
+
+impl Layout {
+    pub fn new(
+        strategy: ShardStrategy,
+        generator: &impl IndexedCommandGenerator,
+        num_shards: usize,
+        weights: &[u32],
+    ) -> Self {
+        let num_shards = num_shards.max(1);
+        let len = generator.len();
+
+        let weights = (0..num_shards)
+            .map(|shard| weights.get(shard).copied().unwrap_or(0) as u64)
+            .collect::<Vec<_>>();
+        let weights = match weights.iter().any(|&weight| weight > 0) {
+            true => weights,
+            false => vec![1; num_shards],
+        };
+
+        let owners = match strategy {
+            ShardStrategy::Modulus if weights.iter().any(|&w| w != weights[0]) => {
+                Some(interleave(&weights, len))
+            }
+            ShardStrategy::Tiles | ShardStrategy::Rows => {
+                Some(owners(strategy, generator, &weights))
+            }
+            ShardStrategy::Modulus | ShardStrategy::Chunk => None,
+        };
+        let assigned = owners.map(|owners| {
+            let mut assigned = vec![Vec::new(); num_shards];
+            for (index, owner) in owners.into_iter().enumerate() {
+                assigned[owner].push(index);
+            }
+            assigned
+        });
+
+        Self {
+            strategy,
+            len,
+            weights,
+            assigned,
+        }
+    }
+
+    /// Indices of all commands of `shard`, in order
+    pub fn indices(&self, shard: usize) -> Vec<usize> {
+        let num_shards = self.weights.len();
+        if shard >= num_shards {
+            return Vec::new();
+        }
+
+        if let Some(assigned) = &self.assigned {
+            return assigned[shard].clone();
+        }
+
+        match self.strategy {
+            ShardStrategy::Chunk => {
+                // the indices whose share of the total weight falls between those of the shards
+                let total = self.weights.iter().sum::<u64>();
+                let before = self.weights[..shard].iter().sum::<u64>();
+                let boundary = |weight: u64| (weight * self.len as u64).div_ceil(total) as usize;
+
+                (boundary(before)..boundary(before + self.weights[shard])).collect()
+            }
+            _ => (shard..self.len).step_by(num_shards).collect(),
+        }
+    }
+}
+
+/// Shard of every command with the tiles and rows strategies, looking up every command once
+fn owners(
+    strategy: ShardStrategy,
+    generator: &impl IndexedCommandGenerator,
+    weights: &[u64],
+) -> Vec<usize> {
+    let positions = (0..generator.len())
+        .map(|index| match generator.command_at(index) {
+            Command::SetPixel(command) => Some(command.coordinates),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let (min_x, min_y, max_x, max_y) = positions.iter().flatten().fold(
+        (u32::MAX, u32::MAX, 0, 0),
+        |(min_x, min_y, max_x, max_y), coordinates| {
+            (
+                min_x.min(coordinates.x),
+                min_y.min(coordinates.y),
+                max_x.max(coordinates.x),
+                max_y.max(coordinates.y),
+            )
+        },
+    );
+    // bounding box of all pixels
+    let (x, y, width, height) = match min_x <= max_x {
+        true => (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1),
+        false => (0, 0, 1, 1),
+    };
+
+    // rows as high as the weight of their tiles, tiles as wide as their weight
+    let (columns, _) = grid(weights.len(), width, height);
+    let rows = weights
+        .chunks(columns)
+        .map(|row| row.iter().sum())
+        .collect::<Vec<_>>();
+
+    positions
+        .into_iter()
+        .enumerate()
+        .map(|(index, coordinates)| match (strategy, coordinates) {
+            (ShardStrategy::Tiles, Some(coordinates)) => {
+                let row = split((coordinates.y - y) as u64, height as u64, &rows);
+                let tiles = &weights[row * columns..(row + 1) * columns];
+
+                row * columns + split((coordinates.x - x) as u64, width as u64, tiles)
+            }
+            (ShardStrategy::Rows, Some(coordinates)) => {
+                split((coordinates.y - y) as u64, height as u64, weights)
+            }
+            // commands without coordinates are spread evenly
+            _ => index % weights.len(),
+        })
+        .collect()
+}
+
+/// Smooth weighted round-robin, so the shards take turns in proportion to their weights at any
+/// length. Equal weights take plain turns.
+fn interleave(weights: &[u64], len: usize) -> Vec<usize> {
+    let total = weights.iter().sum::<u64>() as i64;
+    let mut current = vec![0i64; weights.len()];
+
+    (0..len)
+        .map(|_| {
+            for (current, &weight) in current.iter_mut().zip(weights) {
+                *current += weight as i64;
+            }
+
+            // the first of the largest, so ties go to the lower shard
+            let (shard, _) = current
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|&(_, current)| *current)
+                .unwrap();
+            current[shard] -= total;
+
+            shard
+        })
+        .collect()
+}
+
+/// Index of the part `offset` falls into, when `length` is split into parts proportional to
+/// `weights`
+fn split(offset: u64, length: u64, weights: &[u64]) -> usize {
+    let target = offset * weights.iter().sum::<u64>() / length.max(1);
+    let mut end = 0;
+
+    weights
+        .iter()
+        .position(|&weight| {
+            end += weight;
+            end > target
+        })
+        .unwrap_or(weights.len() - 1)
+}
+
+/// Splits an area into `num_shards` tiles, as close to square as possible
+fn grid(num_shards: usize, width: u32, height: u32) -> (usize, usize) {
+    (1..=num_shards)
+        .filter(|&columns| num_shards.is_multiple_of(columns))
+        .map(|columns| (columns, num_shards / columns))
+        .min_by(|&(a_columns, a_rows), &(b_columns, b_rows)| {
+            let skew = |columns: usize, rows: usize| {
+                let tile_width = width as f64 / columns as f64;
+                let tile_height = height as f64 / rows as f64;
+                (tile_width / tile_height).ln().abs()
+            };
+            skew(a_columns, a_rows).total_cmp(&skew(b_columns, b_rows))
+        })
+        .unwrap_or((1, 1))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::command_generator::shard::Shard;
-    use crate::command_generator::CommandGenerator;
+    use crate::command_generator::shard::{Layout, Shard, ShardStrategy};
+    use crate::command_generator::{CommandGenerator, IndexedCommandGenerator};
     use schwitzerflut_protocol::color::{Color, RgbColor, RgbaColor};
     use schwitzerflut_protocol::command::{Command, SetPixelCommand};
     use schwitzerflut_protocol::coordinates::Coordinates;
+    use std::cell::RefCell;
+    use std::sync::Arc;
 
     struct Generator(Vec<Command>);
 
@@ -50,6 +296,16 @@ mod tests {
         }
     }
 
+    impl IndexedCommandGenerator for Generator {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn command_at(&self, index: usize) -> Command {
+            self.0[index].clone()
+        }
+    }
+
     #[test]
     fn test_shard() {
         let generator = Generator(vec![
@@ -103,6 +359,9 @@ mod tests {
             generator,
             num_shards: 3,
             shard: 0,
+            strategy: ShardStrategy::Modulus,
+            weights: Vec::new(),
+            layout: None,
         };
 
         let expected = vec![
@@ -126,4 +385,212 @@ mod tests {
 
         assert_eq!(expected, shard.commands().collect::<Vec<_>>());
     }
+
+    /// One white pixel for every position in a `width` by `height` area
+    fn grid(width: u32, height: u32) -> Generator {
+        Generator(
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    Command::SetPixel(SetPixelCommand::new(
+                        Coordinates::new(x + 10, y + 20),
+                        Color::Rgb(RgbColor::new(255, 255, 255)),
+                    ))
+                })
+                .collect(),
+        )
+    }
+
+    fn coordinates(shard: &Shard<Generator>) -> Vec<(u32, u32)> {
+        shard
+            .commands()
+            .map(|command| match command {
+                Command::SetPixel(command) => {
+                    (command.coordinates.x - 10, command.coordinates.y - 20)
+                }
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_strategy_covers_all_commands_once() {
+        for strategy in [
+            ShardStrategy::Modulus,
+            ShardStrategy::Chunk,
+            ShardStrategy::Tiles,
+            ShardStrategy::Rows,
+        ] {
+            let mut all = (0..5)
+                .flat_map(|shard| coordinates(&Shard::new(grid(7, 6), shard, 5).strategy(strategy)))
+                .collect::<Vec<_>>();
+            all.sort_by_key(|&(x, y)| (y, x));
+
+            assert_eq!(
+                all,
+                coordinates(&Shard::new(grid(7, 6), 0, 1)),
+                "{strategy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_chunk() {
+        let shard = Shard::new(grid(4, 2), 1, 2).strategy(ShardStrategy::Chunk);
+
+        assert_eq!(coordinates(&shard), vec![(0, 1), (1, 1), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn test_rows() {
+        let shard = Shard::new(grid(2, 6), 1, 3).strategy(ShardStrategy::Rows);
+
+        assert_eq!(coordinates(&shard), vec![(0, 2), (1, 2), (0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn test_tiles() {
+        // a wide image is split into columns first
+        let shard = Shard::new(grid(8, 2), 3, 4).strategy(ShardStrategy::Tiles);
+        assert_eq!(coordinates(&shard), vec![(6, 0), (7, 0), (6, 1), (7, 1)]);
+
+        let shard = Shard::new(grid(4, 4), 3, 4).strategy(ShardStrategy::Tiles);
+        assert_eq!(coordinates(&shard), vec![(2, 2), (3, 2), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn test_weights() {
+        let count = |strategy: ShardStrategy, shard: usize| {
+            coordinates(
+                &Shard::new(grid(6, 6), shard, 3)
+                    .strategy(strategy)
+                    .weights(vec![4, 1, 1]),
+            )
+            .len()
+        };
+
+        for strategy in [
+            ShardStrategy::Modulus,
+            ShardStrategy::Chunk,
+            ShardStrategy::Rows,
+        ] {
+            assert_eq!(
+                [count(strategy, 0), count(strategy, 1), count(strategy, 2)],
+                [24, 6, 6],
+                "{strategy:?}"
+            );
+        }
+
+        // interleaved, instead of runs of the same shard
+        let modulus = Shard::new(grid(6, 1), 0, 3).weights(vec![4, 1, 1]);
+        assert_eq!(coordinates(&modulus), vec![(0, 0), (1, 0), (3, 0), (5, 0)]);
+
+        // large weights still split short images in proportion
+        let modulus = Shard::new(grid(4, 1), 2, 3).weights(vec![500, 500, 1000]);
+        assert_eq!(coordinates(&modulus).len(), 2);
+    }
+
+    #[test]
+    fn test_weighted_tiles() {
+        let tiles = |shard: usize| {
+            coordinates(
+                &Shard::new(grid(8, 8), shard, 4)
+                    .strategy(ShardStrategy::Tiles)
+                    .weights(vec![3, 1, 0, 0]),
+            )
+        };
+
+        // the bottom row has no weight, so the top tiles cover the whole image
+        assert_eq!(tiles(0).len(), 48);
+        assert_eq!(tiles(1).len(), 16);
+        assert!(tiles(2).is_empty() && tiles(3).is_empty());
+    }
+
+    /// Records which commands were looked up
+    struct Recording(Generator, RefCell<Vec<usize>>);
+
+    impl CommandGenerator for Recording {
+        fn commands(&self) -> impl Iterator<Item = Command> {
+            self.0.commands()
+        }
+    }
+
+    impl IndexedCommandGenerator for Recording {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn command_at(&self, index: usize) -> Command {
+            self.1.borrow_mut().push(index);
+            self.0.command_at(index)
+        }
+    }
+
+    #[test]
+    fn test_only_own_commands_are_looked_up() {
+        for (strategy, expected) in [
+            (ShardStrategy::Modulus, vec![1, 4, 7, 10]),
+            (ShardStrategy::Chunk, vec![4, 5, 6, 7]),
+        ] {
+            let recording = Recording(grid(12, 1), RefCell::default());
+            let shard = Shard::new(&recording, 1, 3).strategy(strategy);
+
+            assert_eq!(shard.commands().count(), 4);
+            assert_eq!(*recording.1.borrow(), expected, "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn test_shared_layout() {
+        for strategy in [ShardStrategy::Modulus, ShardStrategy::Tiles] {
+            let recording = Recording(grid(4, 4), RefCell::default());
+            let layout = Arc::new(Layout::new(strategy, &recording, 4, &[3, 1, 1, 1]));
+
+            for shard in 0..4 {
+                let shared = Shard::new(&recording, shard, 4).layout(layout.clone());
+                let own = Shard::new(grid(4, 4), shard, 4)
+                    .strategy(strategy)
+                    .weights(vec![3, 1, 1, 1]);
+
+                assert_eq!(
+                    shared.commands().collect::<Vec<_>>(),
+                    own.commands().collect::<Vec<_>>(),
+                    "{strategy:?}"
+                );
+            }
+
+            // tiles look at every command once for the layout, then once more for its shard
+            let expected = if strategy == ShardStrategy::Tiles {
+                32
+            } else {
+                16
+            };
+            assert_eq!(recording.1.borrow().len(), expected, "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn test_zero_weights_split_evenly() {
+        let shard = Shard::new(grid(4, 1), 1, 2).weights(vec![0, 0]);
+
+        assert_eq!(coordinates(&shard), vec![(1, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn test_shard_out_of_range_is_empty() {
+        for strategy in [
+            ShardStrategy::Modulus,
+            ShardStrategy::Chunk,
+            ShardStrategy::Tiles,
+            ShardStrategy::Rows,
+        ] {
+            let shard = Shard::new(grid(4, 4), 1, 1).strategy(strategy);
+            assert!(coordinates(&shard).is_empty(), "{strategy:?}");
+
+            let weighted = Shard::new(grid(4, 4), 3, 3)
+                .strategy(strategy)
+                .weights(vec![4, 1, 1]);
+            assert!(coordinates(&weighted).is_empty(), "{strategy:?}");
+        }
+    }
 }
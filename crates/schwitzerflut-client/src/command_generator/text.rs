@@ -0,0 +1,63 @@
+use ab_glyph::{point, Font, GlyphId, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use schwitzerflut_protocol::color::RgbColor;
+
+/// Renders every line of `text` below each other, `size` pixels high.
+///
+/// Pixels outside the glyphs are transparent, the edges of glyphs partly transparent, so the
+/// result can go through the same pipeline as any other image.
+pub fn render_text(font: &impl Font, text: &str, size: f32, color: RgbColor) -> RgbaImage {
+    let font = font.as_scaled(PxScale::from(size));
+    let line_height = font.height() + font.line_gap();
+
+    let mut glyphs = Vec::new();
+    let mut width = 0.0f32;
+
+    for (line, content) in text.lines().enumerate() {
+        let baseline = line as f32 * line_height + font.ascent();
+        let mut caret = 0.0;
+        let mut previous: Option<GlyphId> = None;
+
+        for c in content.chars() {
+            let mut glyph = font.scaled_glyph(c);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, glyph.id);
+            }
+            glyph.position = point(caret, baseline);
+            caret += font.h_advance(glyph.id);
+            previous = Some(glyph.id);
+            glyphs.push(glyph);
+        }
+
+        width = width.max(caret);
+    }
+
+    let height = text.lines().count() as f32 * line_height;
+    let mut image = RgbaImage::from_pixel(
+        width.ceil() as u32,
+        height.ceil() as u32,
+        Rgba([color.r, color.g, color.b, 0]),
+    );
+
+    for glyph in glyphs {
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+
+        outline.draw(|x, y, coverage| {
+            let x = bounds.min.x as i64 + x as i64;
+            let y = bounds.min.y as i64 + y as i64;
+            let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else {
+                return;
+            };
+
+            // glyphs may overlap a little, keep the stronger coverage
+            if let Some(pixel) = image.get_pixel_mut_checked(x, y) {
+                pixel[3] = pixel[3].max((coverage.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        });
+    }
+
+    image
+}
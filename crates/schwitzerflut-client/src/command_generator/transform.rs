@@ -0,0 +1,327 @@
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Resampling filter used when scaling or rotating by arbitrary angles.
+///
+/// Rotation only distinguishes `Nearest` from the rest, every other filter rotates bilinearly.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResampleFilter {
+    /// Keeps hard edges, best for pixel art
+    Nearest,
+    Triangle,
+    #[default]
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<ResampleFilter> for FilterType {
+    fn from(filter: ResampleFilter) -> Self {
+        match filter {
+            ResampleFilter::Nearest => Self::Nearest,
+            ResampleFilter::Triangle => Self::Triangle,
+            ResampleFilter::CatmullRom => Self::CatmullRom,
+            ResampleFilter::Gaussian => Self::Gaussian,
+            ResampleFilter::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    /// Mirror left and right
+    Horizontal,
+    /// Mirror top and bottom
+    Vertical,
+}
+
+/// Rectangle cut out of an image, written as `<width>x<height>+<x>+<y>`
+#[derive(Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Crop {
+    type Err = ParseCropError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (size, position) = s.split_once('+').ok_or(ParseCropError::Syntax)?;
+        let (width, height) = size.split_once('x').ok_or(ParseCropError::Syntax)?;
+        let (x, y) = position.split_once('+').ok_or(ParseCropError::Syntax)?;
+
+        Ok(Self {
+            x: x.parse()?,
+            y: y.parse()?,
+            width: width.parse()?,
+            height: height.parse()?,
+        })
+    }
+}
+
+impl TryFrom<String> for Crop {
+    type Error = ParseCropError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for Crop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ParseCropError {
+    #[error("Expected '<width>x<height>+<x>+<y>'")]
+    Syntax,
+
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+}
+
+/// A single step of the pipeline applied to an image before it is turned into commands
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    /// Cuts out a rectangle, clamped to the image
+    Crop(Crop),
+    /// Rotates clockwise. Multiples of 90 degrees are lossless, other angles enlarge the image to
+    /// fit the rotated corners and fill the gaps with transparent pixels
+    Rotate(f32),
+    Flip(Axis),
+    /// Scales to exactly this size
+    Resize {
+        width: u32,
+        height: u32,
+    },
+    /// Scales to the largest size within the bounds that keeps the aspect ratio
+    Fit {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+    /// Repeats every pixel `n` times in both directions
+    Upscale(u32),
+}
+
+impl Transform {
+    pub fn apply(&self, image: DynamicImage, filter: ResampleFilter) -> DynamicImage {
+        match *self {
+            Self::Crop(crop) => image.crop_imm(crop.x, crop.y, crop.width, crop.height),
+            Self::Rotate(degrees) => match degrees.rem_euclid(360.0) {
+                0.0 => image,
+                90.0 => image.rotate90(),
+                180.0 => image.rotate180(),
+                270.0 => image.rotate270(),
+                degrees => DynamicImage::ImageRgba8(rotate(&image.to_rgba8(), degrees, filter)),
+            },
+            Self::Flip(Axis::Horizontal) => image.fliph(),
+            Self::Flip(Axis::Vertical) => image.flipv(),
+            Self::Resize { width, height } => image.resize_exact(width, height, filter.into()),
+            Self::Fit { width, height } => image.resize(
+                width.unwrap_or(u32::MAX),
+                height.unwrap_or(u32::MAX),
+                filter.into(),
+            ),
+            Self::Upscale(factor) => {
+                let factor = factor.max(1);
+                image.resize_exact(
+                    image.width().saturating_mul(factor),
+                    image.height().saturating_mul(factor),
+                    FilterType::Nearest,
+                )
+            }
+        }
+    }
+}
+
+/// Rotates clockwise by an arbitrary angle around the center
+fn rotate(image: &RgbaImage, degrees: f32, filter: ResampleFilter) -> RgbaImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = (image.width() as f32, image.height() as f32);
+
+    let rotated_width = (width * cos.abs() + height * sin.abs()).round().max(1.0) as u32;
+    let rotated_height = (width * sin.abs() + height * cos.abs()).round().max(1.0) as u32;
+
+    let (center_x, center_y) = (width / 2.0, height / 2.0);
+    let (rotated_center_x, rotated_center_y) =
+        (rotated_width as f32 / 2.0, rotated_height as f32 / 2.0);
+
+    RgbaImage::from_fn(rotated_width, rotated_height, |x, y| {
+        // map the center of every target pixel back into the source
+        let dx = x as f32 + 0.5 - rotated_center_x;
+        let dy = y as f32 + 0.5 - rotated_center_y;
+        let source_x = dx * cos + dy * sin + center_x - 0.5;
+        let source_y = -dx * sin + dy * cos + center_y - 0.5;
+
+        match filter {
+            ResampleFilter::Nearest => {
+                pixel(image, source_x.round() as i64, source_y.round() as i64)
+            }
+            _ => bilinear(image, source_x, source_y),
+        }
+    })
+}
+
+fn pixel(image: &RgbaImage, x: i64, y: i64) -> Rgba<u8> {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    *image.get_pixel(x as u32, y as u32)
+}
+
+fn bilinear(image: &RgbaImage, x: f32, y: f32) -> Rgba<u8> {
+    let (left, top) = (x.floor(), y.floor());
+    let (fx, fy) = (x - left, y - top);
+    let (left, top) = (left as i64, top as i64);
+
+    let corners = [
+        (pixel(image, left, top), (1.0 - fx) * (1.0 - fy)),
+        (pixel(image, left + 1, top), fx * (1.0 - fy)),
+        (pixel(image, left, top + 1), (1.0 - fx) * fy),
+        (pixel(image, left + 1, top + 1), fx * fy),
+    ];
+
+    // weigh colors by alpha, so transparent surroundings don't darken the edges
+    let alpha = corners
+        .iter()
+        .map(|(color, weight)| color[3] as f32 * weight)
+        .sum::<f32>();
+    let channel = |channel: usize| {
+        if alpha == 0.0 {
+            return 0;
+        }
+
+        let sum = corners
+            .iter()
+            .map(|(color, weight)| color[channel] as f32 * color[3] as f32 * weight)
+            .sum::<f32>();
+        (sum / alpha).round().clamp(0.0, 255.0) as u8
+    };
+
+    Rgba([channel(0), channel(1), channel(2), alpha.round() as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::transform::{Axis, Crop, ResampleFilter, Transform};
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    /// 3x2 image, red in the top left corner, blue everywhere else
+    fn test_image() -> DynamicImage {
+        let mut image = RgbaImage::from_pixel(3, 2, BLUE);
+        image.put_pixel(0, 0, RED);
+
+        DynamicImage::ImageRgba8(image)
+    }
+
+    fn apply(transform: Transform) -> DynamicImage {
+        transform.apply(test_image(), ResampleFilter::Nearest)
+    }
+
+    #[test]
+    fn test_parse_crop() {
+        assert_eq!(
+            "30x20+5+10".parse(),
+            Ok(Crop {
+                x: 5,
+                y: 10,
+                width: 30,
+                height: 20
+            })
+        );
+        assert!("30x20".parse::<Crop>().is_err());
+    }
+
+    #[test]
+    fn test_crop() {
+        let crop = "2x2+1+0".parse().unwrap();
+        let image = apply(Transform::Crop(crop));
+
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(image.get_pixel(0, 0), BLUE);
+    }
+
+    #[test]
+    fn test_rotate_quarter_turns() {
+        let image = apply(Transform::Rotate(90.0));
+        assert_eq!(image.dimensions(), (2, 3));
+        assert_eq!(image.get_pixel(1, 0), RED);
+
+        let image = apply(Transform::Rotate(-90.0));
+        assert_eq!(image.dimensions(), (2, 3));
+        assert_eq!(image.get_pixel(0, 2), RED);
+
+        assert_eq!(apply(Transform::Rotate(180.0)).get_pixel(2, 1), RED);
+    }
+
+    #[test]
+    fn test_rotate_arbitrary_angle() {
+        let image = RgbaImage::from_pixel(10, 10, RED);
+        let rotated = Transform::Rotate(45.0)
+            .apply(DynamicImage::ImageRgba8(image), ResampleFilter::CatmullRom);
+
+        assert_eq!(rotated.dimensions(), (14, 14));
+        assert_eq!(rotated.get_pixel(7, 7), RED);
+        assert_eq!(rotated.get_pixel(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn test_flip() {
+        assert_eq!(
+            apply(Transform::Flip(Axis::Horizontal)).get_pixel(2, 0),
+            RED
+        );
+        assert_eq!(apply(Transform::Flip(Axis::Vertical)).get_pixel(0, 1), RED);
+    }
+
+    #[test]
+    fn test_resize() {
+        let image = apply(Transform::Resize {
+            width: 6,
+            height: 6,
+        });
+
+        assert_eq!(image.dimensions(), (6, 6));
+    }
+
+    #[test]
+    fn test_fit_keeps_aspect_ratio() {
+        let image = apply(Transform::Fit {
+            width: Some(30),
+            height: Some(30),
+        });
+        assert_eq!(image.dimensions(), (30, 20));
+
+        let image = apply(Transform::Fit {
+            width: None,
+            height: Some(4),
+        });
+        assert_eq!(image.dimensions(), (6, 4));
+    }
+
+    #[test]
+    fn test_upscale() {
+        let image = apply(Transform::Upscale(3));
+
+        assert_eq!(image.dimensions(), (9, 6));
+        assert_eq!(image.get_pixel(2, 2), RED);
+        assert_eq!(image.get_pixel(3, 2), BLUE);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::command_generator::shard::ShardStrategy;
+use crate::dump::Destination;
+use crate::job::{ImageArgs, Job};
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Job file describing several images to draw concurrently
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct JobFile {
+    /// Maximum number of connections shared between all jobs
+    pub max_connections: Option<usize>,
+
+    #[serde(rename = "job")]
+    pub jobs: Vec<JobConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct JobConfig {
+    /// Name used in log output, defaults to the file name of the image
+    pub name: Option<String>,
+
+    /// Path to the image of the `image` generator, relative paths are resolved against the directory
+    /// of the job file. The same goes for `mask`, `exclude_file` and `font`
+    pub image: Option<PathBuf>,
+
+    pub target: Destination,
+
+    /// Shards to draw, defaults to all of them
+    pub shards: Option<Vec<usize>>,
+
+    #[serde(default = "default_num_shards")]
+    pub num_shards: usize,
+
+    #[serde(default)]
+    pub shard_strategy: ShardStrategy,
+
+    /// Relative share of every shard, e.g. to give shards drawn through faster links more pixels
+    #[serde(default)]
+    pub shard_weights: Vec<u32>,
+
+    #[serde(flatten)]
+    pub image_args: ImageArgs,
+
+    /// Keys no option was read from, e.g. misspelled ones
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, toml::Value>,
+}
+
+fn default_num_shards() -> usize {
+    1
+}
+
+impl JobFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read job file {}", path.display()))?;
+
+        Self::parse(&content)
+            .with_context(|| format!("unable to parse job file {}", path.display()))
+    }
+
+    /// Parses a job file, rejecting keys that are not options.
+    ///
+    /// The image options are flattened into every job, which serde can't combine with
+    /// `deny_unknown_fields`, so the remaining keys are collected and checked here instead.
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let file: Self = toml::from_str(content)?;
+
+        for (index, job) in file.jobs.iter().enumerate() {
+            if !job.unknown.is_empty() {
+                let keys = job.unknown.keys().cloned().collect::<Vec<_>>();
+                anyhow::bail!("unknown keys in job {}: {}", index + 1, keys.join(", "));
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Loads the images of all jobs, resolving their paths relative to `base_dir`.
+    pub fn into_jobs(self, base_dir: &Path) -> anyhow::Result<Vec<Job>> {
+        self.jobs
+            .into_iter()
+            .map(|mut job| {
+                let image = job.image.map(|image| base_dir.join(image));
+                job.image_args.mask = job.image_args.mask.map(|mask| base_dir.join(mask));
+                job.image_args.font = job.image_args.font.map(|font| base_dir.join(font));
+                job.image_args.exclude_file =
+                    job.image_args.exclude_file.map(|path| base_dir.join(path));
+                let name = job.name.unwrap_or_else(|| match &image {
+                    Some(image) => image
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into(),
+                    None => job.image_args.generator.clone(),
+                });
+
+                let shards = job.shards.unwrap_or_else(|| (0..job.num_shards).collect());
+
+                let mut loaded = Job::new(
+                    name,
+                    job.target,
+                    image,
+                    job.image_args,
+                    shards,
+                    job.num_shards,
+                    job.shard_strategy,
+                )?;
+                loaded.set_shard_weights(job.shard_weights)?;
+
+                Ok(loaded)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::order::Order;
+    use crate::command_generator::shard::ShardStrategy;
+    use crate::command_generator::transform::{Axis, ResampleFilter};
+    use crate::config::JobFile;
+    use crate::dump::Destination;
+    use crate::stream::transport::{Target, UnixSocket};
+    use schwitzerflut_protocol::color::RgbColor;
+    use std::net::SocketAddr;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_parse_job_file() {
+        let file: JobFile = toml::from_str(
+            r#"
+            max_connections = 4
+
+            [[job]]
+            image = "logo.png"
+            target = "127.0.0.1:1337"
+            offset_x = 10
+            width = 64
+            height = 32
+            num_shards = 4
+            shard_strategy = "tiles"
+            shard_weights = [4, 1, 1, 1]
+
+            [[job]]
+            name = "local"
+            image = "/srv/banner.png"
+            target = "unix:/run/pixelflut.sock"
+            shards = [1]
+            skip_transparent_pixels = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(file.max_connections, Some(4));
+        assert_eq!(file.jobs.len(), 2);
+
+        let logo = &file.jobs[0];
+        assert_eq!(logo.image, Some(PathBuf::from("logo.png")));
+        assert_eq!(
+            logo.target,
+            Destination::Server(Target::Tcp(SocketAddr::from(([127, 0, 0, 1], 1337))))
+        );
+        assert_eq!(logo.shards, None);
+        assert_eq!(logo.num_shards, 4);
+        assert_eq!(logo.shard_strategy, ShardStrategy::Tiles);
+        assert_eq!(logo.shard_weights, vec![4, 1, 1, 1]);
+        assert_eq!(logo.image_args.generator, "image");
+        assert_eq!(logo.image_args.offset_x, 10);
+        assert_eq!(logo.image_args.offset_y, 0);
+        assert_eq!(logo.image_args.width, Some(64));
+        assert_eq!(logo.image_args.height, Some(32));
+        assert!(logo.image_args.skip_transparent_pixels);
+
+        let local = &file.jobs[1];
+        assert_eq!(local.name.as_deref(), Some("local"));
+        assert_eq!(
+            local.target,
+            Destination::Server(Target::Unix(UnixSocket(PathBuf::from(
+                "/run/pixelflut.sock"
+            ))))
+        );
+        assert_eq!(local.shards, Some(vec![1]));
+        assert_eq!(local.num_shards, 1);
+        assert_eq!(local.shard_strategy, ShardStrategy::Modulus);
+        assert!(!local.image_args.skip_transparent_pixels);
+    }
+
+    #[test]
+    fn test_parse_image_options() {
+        let file: JobFile = toml::from_str(
+            r#"
+            [[job]]
+            image = "logo.png"
+            target = "127.0.0.1:1337"
+            crop = "16x16+4+4"
+            flip = ["horizontal"]
+            filter = "nearest"
+            background = "000000"
+            chroma_key = "00ff00"
+            chroma_key_tolerance = 12
+            exclude = ["10x10+0+0", "20,20;30,20;25,30"]
+            exclude_file = "friends.txt"
+            order = "z-order"
+            seed = 7
+            generator = "text"
+            font = "fonts/mono.ttf"
+            font_size = 12.5
+            text_color = "ff0000"
+            "#,
+        )
+        .unwrap();
+
+        let args = &file.jobs[0].image_args;
+        assert_eq!(args.crop, Some("16x16+4+4".parse().unwrap()));
+        assert_eq!(args.flip, vec![Axis::Horizontal]);
+        assert_eq!(args.filter, ResampleFilter::Nearest);
+        assert_eq!(args.background, Some(RgbColor::new(0, 0, 0)));
+        assert_eq!(args.chroma_key, Some(RgbColor::new(0, 0xff, 0)));
+        assert_eq!(args.chroma_key_tolerance, 12);
+        assert_eq!(args.exclude.len(), 2);
+        assert_eq!(args.exclude_file, Some(PathBuf::from("friends.txt")));
+        assert_eq!(args.order, Order::Morton);
+        assert_eq!(args.seed, 7);
+        assert_eq!(args.generator, "text");
+        assert_eq!(args.font, Some(PathBuf::from("fonts/mono.ttf")));
+        assert_eq!(args.font_size, 12.5);
+        assert_eq!(args.text_color, Some(RgbColor::new(0xff, 0, 0)));
+    }
+
+    #[test]
+    fn test_parse_unknown_keys() {
+        let error = JobFile::parse(
+            r#"
+            [[job]]
+            image = "logo.png"
+            target = "127.0.0.1:1337"
+            offest_x = 10
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "unknown keys in job 1: offest_x");
+    }
+
+    #[test]
+    fn test_parse_unknown_generator() {
+        let error = JobFile::parse(
+            r#"
+            [[job]]
+            target = "-"
+            generator = "video"
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("unknown generator 'video', expected one of fill, image, text"));
+    }
+
+    #[test]
+    fn test_invalid_shards() {
+        let jobs = |shards: &str| {
+            JobFile::parse(&format!(
+                r#"
+                [[job]]
+                image = "logo.png"
+                target = "-"
+                {shards}
+                "#
+            ))
+            .unwrap()
+            .into_jobs(Path::new("."))
+        };
+
+        assert!(jobs("num_shards = 0").is_err());
+        let error = jobs("shards = [0, 2]\nnum_shards = 2").err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "job logo.png: shard 2 does not exist, there are 2 shards"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_target() {
+        assert!(toml::from_str::<JobFile>(
+            r#"
+            [[job]]
+            image = "logo.png"
+            target = "localhost"
+            "#,
+        )
+        .is_err())
+    }
+}
@@ -0,0 +1,467 @@
+use crate::cancel::CancellationToken;
+use crate::output::error;
+use crate::runner::RunningJob;
+use crate::stream::stats::ConnectionState;
+use crate::stream::transport::Target;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How long the control server blocks waiting for a request before checking for cancellation
+const RECV_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Largest request body accepted, e.g. of an uploaded image
+const MAX_BODY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Operations offered by the control endpoint
+///
+/// | Method | Path                            | Effect                                                |
+/// |--------|---------------------------------|-------------------------------------------------------|
+/// | GET    | `/jobs`                         | state and statistics of all jobs and connections      |
+/// | POST   | `/jobs/<job>/image`             | draw the image uploaded in the body                   |
+/// | POST   | `/jobs/<job>/image-path`        | draw the image at the path in the body, relative to the image directory |
+/// | POST   | `/jobs/<job>/offset?x=&y=`      | move the image                                        |
+/// | POST   | `/jobs/<job>/size?width=&height=` | scale the image, original size without parameters   |
+/// | POST   | `/jobs/<job>/pause[?connection=]`  | pause all or a single connection                   |
+/// | POST   | `/jobs/<job>/resume[?connection=]` | resume all or a single connection                  |
+/// | POST   | `/jobs/<job>/rate-limit[?connection=][&bytes_per_second=]` | limit throughput, unlimited without a value |
+#[derive(Debug, Eq, PartialEq)]
+enum Route {
+    Jobs,
+    UploadImage {
+        job: usize,
+    },
+    SelectImage {
+        job: usize,
+    },
+    Offset {
+        job: usize,
+        x: u32,
+        y: u32,
+    },
+    Size {
+        job: usize,
+        size: Option<(u32, u32)>,
+    },
+    Pause {
+        job: usize,
+        connection: Option<usize>,
+    },
+    Resume {
+        job: usize,
+        connection: Option<usize>,
+    },
+    RateLimit {
+        job: usize,
+        connection: Option<usize>,
+        bytes_per_second: Option<u64>,
+    },
+}
+
+#[derive(Error, Debug)]
+enum ControlError {
+    #[error("not found")]
+    NotFound,
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("request body is larger than {MAX_BODY_SIZE} bytes")]
+    TooLarge,
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0:#}")]
+    Failed(#[from] anyhow::Error),
+}
+
+impl ControlError {
+    fn status_code(&self) -> u16 {
+        match self {
+            Self::NotFound => 404,
+            Self::Forbidden(_) => 403,
+            Self::TooLarge => 413,
+            Self::BadRequest(_) => 400,
+            Self::Failed(_) => 422,
+        }
+    }
+}
+
+impl Route {
+    fn parse(method: &Method, url: &str) -> Result<Self, ControlError> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let query = parse_query(query);
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["jobs"]) => Ok(Self::Jobs),
+            (Method::Post, ["jobs", job, action]) => {
+                let job = job.parse().map_err(|_| ControlError::NotFound)?;
+                let connection = optional(&query, "connection")?;
+
+                match *action {
+                    "image" => Ok(Self::UploadImage { job }),
+                    "image-path" => Ok(Self::SelectImage { job }),
+                    "offset" => Ok(Self::Offset {
+                        job,
+                        x: required(&query, "x")?,
+                        y: required(&query, "y")?,
+                    }),
+                    "size" => match (optional(&query, "width")?, optional(&query, "height")?) {
+                        (Some(width), Some(height)) => Ok(Self::Size {
+                            job,
+                            size: Some((width, height)),
+                        }),
+                        (None, None) => Ok(Self::Size { job, size: None }),
+                        _ => Err(ControlError::BadRequest(
+                            "width and height have to be given together".into(),
+                        )),
+                    },
+                    "pause" => Ok(Self::Pause { job, connection }),
+                    "resume" => Ok(Self::Resume { job, connection }),
+                    "rate-limit" => Ok(Self::RateLimit {
+                        job,
+                        connection,
+                        bytes_per_second: optional(&query, "bytes_per_second")?,
+                    }),
+                    _ => Err(ControlError::NotFound),
+                }
+            }
+            _ => Err(ControlError::NotFound),
+        }
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<&str, &str> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .collect()
+}
+
+fn optional<T: FromStr>(query: &HashMap<&str, &str>, key: &str) -> Result<Option<T>, ControlError> {
+    query
+        .get(key)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ControlError::BadRequest(format!("invalid value for {key}")))
+        })
+        .transpose()
+}
+
+fn required<T: FromStr>(query: &HashMap<&str, &str>, key: &str) -> Result<T, ControlError> {
+    optional(query, key)?.ok_or_else(|| ControlError::BadRequest(format!("missing {key}")))
+}
+
+#[derive(Serialize)]
+struct JobStatus {
+    name: String,
+    image: Option<PathBuf>,
+    offset_x: u32,
+    offset_y: u32,
+    width: Option<u32>,
+    height: Option<u32>,
+    connections: Vec<ConnectionStatus>,
+}
+
+#[derive(Serialize)]
+struct ConnectionStatus {
+    label: String,
+    shards: Vec<usize>,
+    state: ConnectionState,
+    bytes_sent: u64,
+    payloads_sent: u64,
+    reconnects: u64,
+    paused: bool,
+    rate_limit: Option<u64>,
+}
+
+impl JobStatus {
+    fn of(job: &RunningJob) -> Self {
+        let connections = job
+            .connections()
+            .iter()
+            .map(|connection| ConnectionStatus {
+                label: connection.label.clone(),
+                shards: connection.shards.clone(),
+                state: connection.stats.state(),
+                bytes_sent: connection.stats.bytes_sent(),
+                payloads_sent: connection.stats.payloads_sent(),
+                reconnects: connection.stats.reconnects(),
+                paused: connection.throttle.is_paused(),
+                rate_limit: connection.throttle.rate_limit(),
+            })
+            .collect();
+
+        job.inspect(|job| Self {
+            name: job.name.clone(),
+            image: job.image.clone(),
+            offset_x: job.image_args.offset_x,
+            offset_y: job.image_args.offset_y,
+            width: job.image_args.width,
+            height: job.image_args.height,
+            connections,
+        })
+    }
+}
+
+/// Reads the body of a request, failing if it is larger than [`MAX_BODY_SIZE`]
+fn read_body(request: &mut Request) -> Result<Vec<u8>, ControlError> {
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| ControlError::BadRequest(e.to_string()))?;
+
+    match body.len() as u64 > MAX_BODY_SIZE {
+        true => Err(ControlError::TooLarge),
+        false => Ok(body),
+    }
+}
+
+/// Resolves a path relative to `directory`, rejecting paths that lead outside of it
+fn resolve_within(directory: &Path, path: &str) -> Result<PathBuf, ControlError> {
+    let directory = directory.canonicalize().map_err(anyhow::Error::from)?;
+    let resolved = directory
+        .join(path)
+        .canonicalize()
+        .map_err(|e| ControlError::BadRequest(format!("unable to open {path}: {e}")))?;
+
+    match resolved.starts_with(&directory) {
+        true => Ok(resolved),
+        false => Err(ControlError::Forbidden(format!(
+            "{path} is outside of the image directory"
+        ))),
+    }
+}
+
+/// Local HTTP endpoint to change running jobs
+pub struct ControlServer {
+    server: Server,
+    jobs: Vec<Arc<RunningJob>>,
+    image_dir: Option<PathBuf>,
+}
+
+impl ControlServer {
+    /// Listens on a loopback address or a unix socket, as anyone who can connect controls the jobs
+    pub fn bind(listen: &Target, jobs: Vec<Arc<RunningJob>>) -> anyhow::Result<Self> {
+        let server = match listen {
+            Target::Tcp(addr) if !addr.ip().is_loopback() => {
+                return Err(anyhow!(
+                    "the control endpoint only listens on localhost, not on {addr}"
+                ))
+            }
+            Target::Tcp(addr) => Server::http(addr),
+            #[cfg(unix)]
+            Target::Unix(socket) => Server::http_unix(&socket.0),
+        }
+        .map_err(|e| anyhow!("unable to listen on {listen}: {e}"))?;
+
+        Ok(Self {
+            server,
+            jobs,
+            image_dir: None,
+        })
+    }
+
+    /// Directory images may be selected from by path. Without one, images can only be uploaded.
+    pub fn image_dir(mut self, directory: Option<PathBuf>) -> Self {
+        self.image_dir = directory;
+        self
+    }
+
+    /// Serves requests on a background thread until the token is cancelled.
+    pub fn spawn(self, cancellation: CancellationToken) {
+        std::thread::spawn(move || {
+            while !cancellation.is_cancelled() {
+                match self.server.recv_timeout(RECV_TIMEOUT) {
+                    Ok(Some(request)) => self.respond(request),
+                    Ok(None) => {}
+                    Err(e) => error!("control endpoint: {}", e),
+                }
+            }
+        });
+    }
+
+    fn respond(&self, mut request: Request) {
+        let response = match self.handle(&mut request) {
+            Ok(Some(body)) => Response::from_string(body)
+                .with_header("Content-Type: application/json".parse::<Header>().unwrap()),
+            Ok(None) => Response::from_string("").with_status_code(204),
+            Err(e) => Response::from_string(e.to_string()).with_status_code(e.status_code()),
+        };
+
+        if let Err(e) = request.respond(response) {
+            error!("control endpoint: unable to respond: {}", e);
+        }
+    }
+
+    /// Executes a request, returning the JSON body of the response if there is one
+    fn handle(&self, request: &mut Request) -> Result<Option<String>, ControlError> {
+        let route = Route::parse(request.method(), request.url())?;
+
+        let job = |index: usize| self.jobs.get(index).ok_or(ControlError::NotFound);
+        let throttles = |index: usize, connection: Option<usize>| {
+            let connections = job(index)?.connections();
+
+            match connection {
+                None => Ok(connections.iter().map(|c| c.throttle.clone()).collect()),
+                Some(n) => connections
+                    .get(n)
+                    .map(|c| vec![c.throttle.clone()])
+                    .ok_or(ControlError::NotFound),
+            }
+        };
+
+        match route {
+            Route::Jobs => {
+                let status = self
+                    .jobs
+                    .iter()
+                    .map(|job| JobStatus::of(job))
+                    .collect::<Vec<_>>();
+                let body = serde_json::to_string(&status).map_err(anyhow::Error::from)?;
+
+                return Ok(Some(body));
+            }
+            Route::UploadImage { job: index } => {
+                let body = read_body(request)?;
+                let image = image::load_from_memory(&body).map_err(|e| {
+                    ControlError::BadRequest(format!("unable to decode image: {e}"))
+                })?;
+
+                job(index)?.update(|job| job.set_image(image))?;
+            }
+            Route::SelectImage { job: index } => {
+                let directory = self.image_dir.as_deref().ok_or_else(|| {
+                    ControlError::Forbidden("no image directory is configured".into())
+                })?;
+                let path = String::from_utf8(read_body(request)?)
+                    .map_err(|e| ControlError::BadRequest(e.to_string()))?;
+                let path = resolve_within(directory, path.trim())?;
+
+                job(index)?.update(|job| job.select_image(path))?;
+            }
+            Route::Offset { job: index, x, y } => {
+                job(index)?.update(|job| {
+                    job.image_args.offset_x = x;
+                    job.image_args.offset_y = y;
+                    job.rebuild()
+                })?;
+            }
+            Route::Size { job: index, size } => {
+                job(index)?.update(|job| {
+                    job.image_args.width = size.map(|(width, _)| width);
+                    job.image_args.height = size.map(|(_, height)| height);
+                    job.rebuild()
+                })?;
+            }
+            Route::Pause { job, connection } => {
+                throttles(job, connection)?.iter().for_each(|t| t.pause());
+            }
+            Route::Resume { job, connection } => {
+                throttles(job, connection)?.iter().for_each(|t| t.resume());
+            }
+            Route::RateLimit {
+                job,
+                connection,
+                bytes_per_second,
+            } => {
+                throttles(job, connection)?
+                    .iter()
+                    .for_each(|t| t.set_rate_limit(bytes_per_second));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::control::{resolve_within, ControlError, ControlServer, Route};
+    use tiny_http::Method;
+
+    #[test]
+    fn test_parse_jobs() {
+        assert_eq!(Route::parse(&Method::Get, "/jobs").unwrap(), Route::Jobs)
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(
+            Route::parse(&Method::Post, "/jobs/1/offset?x=10&y=20").unwrap(),
+            Route::Offset {
+                job: 1,
+                x: 10,
+                y: 20
+            }
+        )
+    }
+
+    #[test]
+    fn test_parse_pause_single_connection() {
+        assert_eq!(
+            Route::parse(&Method::Post, "/jobs/0/pause?connection=2").unwrap(),
+            Route::Pause {
+                job: 0,
+                connection: Some(2)
+            }
+        )
+    }
+
+    #[test]
+    fn test_parse_size_without_height() {
+        assert!(matches!(
+            Route::parse(&Method::Post, "/jobs/0/size?width=10"),
+            Err(ControlError::BadRequest(_))
+        ))
+    }
+
+    #[test]
+    fn test_resolve_within_image_dir() {
+        let directory = std::env::temp_dir().join(format!("control-test-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("images")).unwrap();
+        std::fs::write(directory.join("images/logo.png"), b"").unwrap();
+        std::fs::write(directory.join("secret.txt"), b"").unwrap();
+        let images = directory.join("images");
+
+        assert_eq!(
+            resolve_within(&images, "logo.png").unwrap(),
+            images.join("logo.png").canonicalize().unwrap()
+        );
+        for path in ["../secret.txt", "/etc/passwd"] {
+            assert!(matches!(
+                resolve_within(&images, path),
+                Err(ControlError::Forbidden(_))
+            ));
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_bind_only_to_loopback() {
+        assert!(ControlServer::bind(&"0.0.0.0:0".parse().unwrap(), Vec::new()).is_err());
+        assert!(ControlServer::bind(&"127.0.0.1:0".parse().unwrap(), Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_parse_unknown_route() {
+        assert!(matches!(
+            Route::parse(&Method::Get, "/jobs/0/offset"),
+            Err(ControlError::NotFound)
+        ))
+    }
+}
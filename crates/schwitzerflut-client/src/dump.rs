@@ -0,0 +1,302 @@
+use crate::cancel::CancellationToken;
+use crate::job::{assemble, Job};
+use crate::output::error;
+use crate::stream::payload::Payload;
+use crate::stream::transport::{ParseTargetError, Target};
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Placeholder in file templates replaced with the shard number, producing one file per shard
+const SHARD_PLACEHOLDER: &str = "{shard}";
+
+/// Placeholder in file templates replaced with the index of the job
+const JOB_PLACEHOLDER: &str = "{job}";
+
+/// Destination of a dry run writing the generated commands instead of sending them
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Output {
+    Stdout,
+    /// Path template that may contain `{shard}` and `{job}` placeholders
+    File(String),
+}
+
+impl Output {
+    fn per_shard(&self) -> bool {
+        matches!(self, Self::File(template) if template.contains(SHARD_PLACEHOLDER))
+    }
+
+    fn path(template: &str, job: usize, shard: Option<usize>) -> PathBuf {
+        let path = template.replace(JOB_PLACEHOLDER, &job.to_string());
+
+        match shard {
+            Some(shard) => path.replace(SHARD_PLACEHOLDER, &shard.to_string()),
+            None => path,
+        }
+        .into()
+    }
+
+    /// Writes the payloads of a job `loops` times, stopping early once `cancellation` is cancelled.
+    ///
+    /// `index` is the position of the job, used to fill in the `{job}` placeholder. Animations have
+    /// no timing in a dump, so only their first frame is written.
+    pub fn write(
+        &self,
+        job: &Job,
+        index: usize,
+        loops: u64,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        if !job.frames.is_empty() {
+            // stderr, as the commands might be written to stdout
+            error!(
+                "{}: only writing the first of {} frames of the animation",
+                job.name,
+                job.frames.len()
+            );
+        }
+
+        if self.per_shard() {
+            let mut result = Ok(());
+
+            // files are written while the remaining shards are still rendering
+            job.render_shards(&job.shards, |shard, payload| {
+                if result.is_ok() {
+                    result = self.write_payload(
+                        index,
+                        Some(shard),
+                        &Payload::new(vec![payload]),
+                        loops,
+                        cancellation,
+                    );
+                }
+            });
+
+            result
+        } else {
+            let payload = assemble(&job.render_all(), &job.shards);
+
+            self.write_payload(index, None, &payload, loops, cancellation)
+        }
+    }
+
+    fn write_payload(
+        &self,
+        job: usize,
+        shard: Option<usize>,
+        payload: &Payload,
+        loops: u64,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let (mut writer, name): (Box<dyn Write>, _) = match self {
+            Self::Stdout => (Box::new(io::stdout().lock()), "stdout".to_string()),
+            Self::File(template) => {
+                let path = Self::path(template, job, shard);
+                let file = File::create(&path)
+                    .with_context(|| format!("unable to create {}", path.display()))?;
+
+                (Box::new(BufWriter::new(file)), path.display().to_string())
+            }
+        };
+
+        let segments = (0..loops).flat_map(|_| payload.segments());
+
+        for segment in segments {
+            if cancellation.is_cancelled() {
+                break;
+            }
+
+            writer
+                .write_all(segment)
+                .with_context(|| format!("unable to write to {}", name))?;
+        }
+
+        writer
+            .flush()
+            .with_context(|| format!("unable to write to {}", name))
+    }
+}
+
+/// Fails if several jobs would write to the same files, each one truncating those of the others.
+///
+/// Only templates with a `{job}` placeholder can be shared, stdout is written to in turn.
+pub fn check_outputs<'a>(outputs: impl IntoIterator<Item = &'a Output>) -> anyhow::Result<()> {
+    let mut seen = HashSet::new();
+
+    for output in outputs {
+        if let Output::File(template) = output {
+            if !template.contains(JOB_PLACEHOLDER) && !seen.insert(template) {
+                anyhow::bail!(
+                    "several jobs write to {output}, add {JOB_PLACEHOLDER} to write one file per job"
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stdout => write!(f, "-"),
+            Self::File(template) => write!(f, "file:{template}"),
+        }
+    }
+}
+
+/// Where the commands of a job go, a server or an output for dry runs.
+///
+/// Parses like a [`Target`], except that `-` writes to stdout and `file:<template>` writes to
+/// files instead.
+#[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Destination {
+    Server(Target),
+    Output(Output),
+}
+
+impl FromStr for Destination {
+    type Err = ParseDestinationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(Self::Output(Output::Stdout));
+        }
+
+        match s.strip_prefix("file:") {
+            Some("") => Err(Self::Err::EmptyPath),
+            Some(template) => Ok(Self::Output(Output::File(template.into()))),
+            None => Ok(Self::Server(s.parse()?)),
+        }
+    }
+}
+
+impl TryFrom<String> for Destination {
+    type Error = ParseDestinationError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Server(target) => write!(f, "{target}"),
+            Self::Output(output) => write!(f, "{output}"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ParseDestinationError {
+    #[error("Expected a socket address, 'unix:<path>', 'file:<path>' or '-'")]
+    InvalidAddress,
+
+    #[error("Path must not be empty")]
+    EmptyPath,
+
+    #[error(transparent)]
+    Target(ParseTargetError),
+}
+
+impl From<ParseTargetError> for ParseDestinationError {
+    fn from(error: ParseTargetError) -> Self {
+        match error {
+            ParseTargetError::InvalidAddress(_) => Self::InvalidAddress,
+            error => Self::Target(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cancel::CancellationToken;
+    use crate::dump::{check_outputs, Destination, Output, ParseDestinationError};
+    use crate::stream::payload::Payload;
+    use crate::stream::transport::Target;
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_per_shard() {
+        assert!(Output::File("out/{shard}.txt".into()).per_shard());
+        assert!(!Output::File("out.txt".into()).per_shard());
+        assert!(!Output::Stdout.per_shard());
+    }
+
+    #[test]
+    fn test_path() {
+        assert_eq!(
+            Output::path("out/job-{job}-shard-{shard}.txt", 1, Some(3)),
+            PathBuf::from("out/job-1-shard-3.txt")
+        );
+        assert_eq!(
+            Output::path("out/job-{job}.txt", 0, None),
+            PathBuf::from("out/job-0.txt")
+        );
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        assert_eq!(
+            "127.0.0.1:1337".parse(),
+            Ok(Destination::Server(Target::Tcp(SocketAddr::from((
+                [127, 0, 0, 1],
+                1337
+            )))))
+        );
+        assert_eq!("-".parse(), Ok(Destination::Output(Output::Stdout)));
+        assert_eq!(
+            "file:out/{shard}.txt".parse(),
+            Ok(Destination::Output(Output::File("out/{shard}.txt".into())))
+        );
+        assert_eq!(
+            "file:".parse::<Destination>(),
+            Err(ParseDestinationError::EmptyPath)
+        );
+        assert_eq!(
+            "localhost".parse::<Destination>(),
+            Err(ParseDestinationError::InvalidAddress)
+        );
+    }
+
+    #[test]
+    fn test_check_outputs() {
+        let file = |template: &str| Output::File(template.into());
+
+        assert!(check_outputs(&[Output::Stdout, Output::Stdout]).is_ok());
+        assert!(check_outputs(&[file("out-{job}.txt"), file("out-{job}.txt")]).is_ok());
+        assert!(check_outputs(&[file("a.txt"), file("b.txt")]).is_ok());
+        assert!(check_outputs(&[file("out.txt"), Output::Stdout, file("out.txt")]).is_err());
+        assert!(check_outputs(&[file("{shard}.txt"), file("{shard}.txt")]).is_err());
+    }
+
+    #[test]
+    fn test_write_stops_when_cancelled() {
+        let path = std::env::temp_dir().join(format!("dump-test-{}.txt", std::process::id()));
+        let output = Output::File(path.display().to_string());
+        let payload = Payload::from(b"PX 0 0 ffffff\n".to_vec());
+        let cancellation = CancellationToken::default();
+
+        output
+            .write_payload(0, None, &payload, 2, &cancellation)
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"PX 0 0 ffffff\n".repeat(2));
+
+        cancellation.cancel();
+        output
+            .write_payload(0, None, &payload, u64::MAX, &cancellation)
+            .unwrap();
+        assert!(std::fs::read(&path).unwrap().is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
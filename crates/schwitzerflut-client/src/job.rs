@@ -0,0 +1,1198 @@
+use crate::command_generator::adjust::Adjustment;
+use crate::command_generator::animation::{open_frames, Frame};
+use crate::command_generator::exclude::{Exclude, Exclusions, Region};
+use crate::command_generator::fill::Fill;
+use crate::command_generator::image::{ImageSource, ImageSourceBuilder};
+use crate::command_generator::order::Order;
+use crate::command_generator::registry::{Registry, UnknownGeneratorError};
+use crate::command_generator::shard::{Layout, Shard, ShardStrategy};
+use crate::command_generator::text::render_text;
+use crate::command_generator::transform::{Axis, Crop, ResampleFilter};
+use crate::command_generator::{CommandGenerator, DynCommandGenerator};
+use crate::dump::Destination;
+use crate::stream::payload::{LineEnding, Payload, PayloadBuilder};
+use ab_glyph::FontVec;
+use anyhow::Context;
+use clap::Args;
+use image::{DynamicImage, Rgba, RgbaImage};
+use schwitzerflut_protocol::color::{Color, RgbColor};
+use schwitzerflut_protocol::command::Command;
+use schwitzerflut_protocol::coordinates::Coordinates;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Options describing how an image is turned into commands.
+///
+/// Shared between the command line and the job file, so every option is available in both.
+#[derive(Args, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ImageArgs {
+    /// What draws the job: `image` draws the image at the given path, `text` renders `--input` in
+    /// `--font` and `fill` covers `--width` by `--height` pixels in the `rrggbb` or `rrggbbaa`
+    /// colour given as `--input`
+    #[arg(long, env, default_value = IMAGE_GENERATOR, value_parser = parse_generator)]
+    #[serde(deserialize_with = "deserialize_generator")]
+    pub generator: String,
+
+    /// Text of the `text` generator or colour of the `fill` generator
+    #[arg(long, env)]
+    pub input: Option<String>,
+
+    /// TrueType or OpenType font of the `text` generator
+    #[arg(long, env)]
+    pub font: Option<PathBuf>,
+
+    /// Height of a line of text in pixels
+    #[arg(long, env, default_value_t = 32.0)]
+    pub font_size: f32,
+
+    /// `rrggbb` colour of the text, white by default
+    #[arg(long, env)]
+    #[serde(deserialize_with = "parse_optional")]
+    pub text_color: Option<RgbColor>,
+
+    #[arg(long, env, default_value_t = 0)]
+    pub offset_x: u32,
+    #[arg(long, env, default_value_t = 0)]
+    pub offset_y: u32,
+
+    #[arg(long, env, required = false)]
+    pub height: Option<u32>,
+
+    #[arg(long, env, required = false)]
+    pub width: Option<u32>,
+
+    /// Whether to send set pixel commands for transparent pixels
+    #[arg(long, env, default_value_t = true)]
+    pub skip_transparent_pixels: bool,
+
+    /// Terminator written after every command
+    #[arg(long, env, value_enum, default_value_t = LineEnding::Lf)]
+    pub line_ending: LineEnding,
+
+    /// Cut out `<width>x<height>+<x>+<y>` of the image before any other transform
+    #[arg(long, env)]
+    pub crop: Option<Crop>,
+
+    /// Rotate clockwise by this many degrees, after cropping
+    #[arg(long, env, allow_negative_numbers = true)]
+    pub rotate: Option<f32>,
+
+    /// Mirror the image after rotating, `horizontal`, `vertical` or both
+    #[arg(long, env, value_enum, value_delimiter = ',')]
+    pub flip: Vec<Axis>,
+
+    /// Scale into `--width` and `--height` keeping the aspect ratio. Either of them may be left out
+    #[arg(long, env)]
+    pub fit: bool,
+
+    /// Repeat every pixel this many times after scaling, for pixel art
+    #[arg(long, env)]
+    pub upscale: Option<u32>,
+
+    /// Filter used for scaling and rotating. Rotations by angles other than multiples of 90 degrees
+    /// use `nearest` as is and bilinear interpolation for every other filter
+    #[arg(long, env, value_enum, default_value_t = ResampleFilter::CatmullRom)]
+    pub filter: ResampleFilter,
+
+    /// Gamma correction, values above 1 brighten the midtones. Colour adjustments run after all
+    /// transforms, in the order they are listed here
+    #[arg(long, env)]
+    pub gamma: Option<f32>,
+
+    /// Multiply all channels, e.g. `0.8` to dim the image
+    #[arg(long, env)]
+    pub brightness: Option<f32>,
+
+    /// Spread colours away from middle grey, `1` keeps them unchanged
+    #[arg(long, env)]
+    pub contrast: Option<f32>,
+
+    /// `0` removes all colour, `1` keeps it unchanged, above `1` intensifies it
+    #[arg(long, env)]
+    pub saturation: Option<f32>,
+
+    /// Rotate hues by this many degrees
+    #[arg(long, env, allow_negative_numbers = true)]
+    pub hue_rotate: Option<f32>,
+
+    /// Convert to shades of grey
+    #[arg(long, env)]
+    pub grayscale: bool,
+
+    /// Invert all colours
+    #[arg(long, env)]
+    pub invert: bool,
+
+    /// Pixels with a lower alpha count as transparent, `1` only skips fully transparent pixels
+    #[arg(long, env, default_value_t = 1)]
+    pub alpha_threshold: u8,
+
+    /// Blend translucent pixels onto this `rrggbb` colour and send plain RGB, for servers that
+    /// ignore alpha
+    #[arg(long, env)]
+    #[serde(deserialize_with = "parse_optional")]
+    pub background: Option<RgbColor>,
+
+    /// Treat pixels of this `rrggbb` colour as transparent
+    #[arg(long, env)]
+    #[serde(deserialize_with = "parse_optional")]
+    pub chroma_key: Option<RgbColor>,
+
+    /// Largest difference in any channel that still matches `--chroma-key`
+    #[arg(long, env, default_value_t = 0)]
+    pub chroma_key_tolerance: u8,
+
+    /// Greyscale image, only pixels where it is not black are drawn. Brighter pixels are drawn
+    /// first. Scaled to the size of the image after all transforms
+    #[arg(long, env)]
+    pub mask: Option<PathBuf>,
+
+    /// Order the pixels are drawn in
+    #[arg(long, env, value_enum, default_value_t = Order::RowMajor)]
+    pub order: Order,
+
+    /// Seed of the `shuffle` order
+    #[arg(long, env, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Send the pixels on the strongest edges up to this many times per pass, the others
+    /// proportionally less often
+    #[arg(long, env, default_value_t = 1)]
+    pub importance_repeats: u32,
+
+    /// Never draw into this region of the canvas, `<width>x<height>+<x>+<y>` or a polygon as
+    /// `<x>,<y>;<x>,<y>;...`. Can be repeated
+    #[arg(long)]
+    pub exclude: Vec<Region>,
+
+    /// File with one region to exclude per line, in the format of `--exclude`
+    #[arg(long, env)]
+    pub exclude_file: Option<PathBuf>,
+}
+
+impl Default for ImageArgs {
+    fn default() -> Self {
+        Self {
+            generator: IMAGE_GENERATOR.to_string(),
+            input: None,
+            font: None,
+            font_size: 32.0,
+            text_color: None,
+            offset_x: 0,
+            offset_y: 0,
+            height: None,
+            width: None,
+            skip_transparent_pixels: true,
+            line_ending: LineEnding::Lf,
+            crop: None,
+            rotate: None,
+            flip: Vec::new(),
+            fit: false,
+            upscale: None,
+            filter: ResampleFilter::CatmullRom,
+            gamma: None,
+            brightness: None,
+            contrast: None,
+            saturation: None,
+            hue_rotate: None,
+            grayscale: false,
+            invert: false,
+            alpha_threshold: 1,
+            background: None,
+            chroma_key: None,
+            chroma_key_tolerance: 0,
+            mask: None,
+            order: Order::RowMajor,
+            seed: 0,
+            importance_repeats: 1,
+            exclude: Vec::new(),
+            exclude_file: None,
+        }
+    }
+}
+
+/// Reads an optional value from its string form, e.g. a colour as `rrggbb`
+fn parse_optional<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| value.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Accepts the names of [`generators`] only, so typos are reported before anything is loaded
+fn parse_generator(name: &str) -> Result<String, UnknownGeneratorError> {
+    generators().check(name)?;
+    Ok(name.to_string())
+}
+
+fn deserialize_generator<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    parse_generator(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+pub fn open_image(path: &Path) -> anyhow::Result<DynamicImage> {
+    image::open(path).with_context(|| format!("unable to load image from {}", path.display()))
+}
+
+fn open_font(path: &Path) -> anyhow::Result<FontVec> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("unable to read font from {}", path.display()))?;
+
+    FontVec::try_from_vec(data).with_context(|| format!("unable to parse font {}", path.display()))
+}
+
+fn read_exclusions(path: &Path) -> anyhow::Result<Exclusions> {
+    std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read exclusions from {}", path.display()))?
+        .parse()
+        .with_context(|| format!("unable to parse exclusions in {}", path.display()))
+}
+
+impl ImageArgs {
+    pub fn source(
+        &self,
+        image: DynamicImage,
+        mask: Option<&DynamicImage>,
+    ) -> anyhow::Result<ImageSource> {
+        let mut builder = ImageSourceBuilder::new(image)
+            .offset(Coordinates::new(self.offset_x, self.offset_y))
+            .include_transparent_pixels(!self.skip_transparent_pixels)
+            .filter(self.filter)
+            .order(self.order)
+            .seed(self.seed)
+            .importance_repeats(self.importance_repeats);
+
+        if let Some(crop) = self.crop {
+            builder = builder.crop(crop);
+        }
+
+        if let Some(degrees) = self.rotate {
+            builder = builder.rotate(degrees);
+        }
+
+        for &axis in &self.flip {
+            builder = builder.flip(axis);
+        }
+
+        match (self.width, self.height) {
+            (None, None) => {}
+            (width, height) if self.fit => builder = builder.fit(width, height),
+            (Some(x), Some(y)) => builder = builder.resize((x, y)),
+            _ => anyhow::bail!("width and height have to be given together unless fit is set"),
+        }
+
+        if let Some(factor) = self.upscale {
+            builder = builder.upscale(factor);
+        }
+
+        let adjustments = [
+            self.gamma.map(Adjustment::Gamma),
+            self.brightness.map(Adjustment::Brightness),
+            self.contrast.map(Adjustment::Contrast),
+            self.saturation.map(Adjustment::Saturation),
+            self.hue_rotate.map(Adjustment::HueRotate),
+            self.grayscale.then_some(Adjustment::Grayscale),
+            self.invert.then_some(Adjustment::Invert),
+        ];
+
+        for adjustment in adjustments.into_iter().flatten() {
+            builder = builder.adjust(adjustment);
+        }
+
+        builder = builder.alpha_threshold(self.alpha_threshold);
+
+        if let Some(background) = self.background {
+            builder = builder.background(background);
+        }
+
+        if let Some(color) = self.chroma_key {
+            builder = builder.chroma_key(color, self.chroma_key_tolerance);
+        }
+
+        if let Some(mask) = mask {
+            builder = builder.mask(mask.clone());
+        }
+
+        Ok(builder.build())
+    }
+
+    /// Regions given directly and those read from `exclude_file`
+    pub fn exclusions(&self) -> anyhow::Result<Exclusions> {
+        let mut exclusions = Exclusions(self.exclude.clone());
+
+        if let Some(path) = &self.exclude_file {
+            exclusions.0.extend(read_exclusions(path)?.0);
+        }
+
+        Ok(exclusions)
+    }
+}
+
+/// Name of the generator drawing images from disk, the default
+pub const IMAGE_GENERATOR: &str = "image";
+
+/// What the generators of a job are created from
+pub struct GeneratorInput<'a> {
+    pub args: &'a ImageArgs,
+    /// Decoded image of the `image` generator
+    pub original: Option<&'a DynamicImage>,
+    pub mask: Option<&'a DynamicImage>,
+    /// Loaded font of the `text` generator
+    pub font: Option<&'a FontVec>,
+}
+
+/// All generators a job can be drawn with, by name
+pub fn generators<'a>() -> Registry<GeneratorInput<'a>> {
+    Registry::new()
+        .register(IMAGE_GENERATOR, image_generator)
+        .register("text", text_generator)
+        .register("fill", fill_generator)
+}
+
+fn image_generator(input: &GeneratorInput) -> anyhow::Result<Box<dyn DynCommandGenerator>> {
+    let original = input.original.context("no image loaded")?;
+
+    Ok(Box::new(input.args.source(original.clone(), input.mask)?))
+}
+
+fn text_generator(input: &GeneratorInput) -> anyhow::Result<Box<dyn DynCommandGenerator>> {
+    let font = input.font.context("the text generator needs a font")?;
+    let content = input
+        .args
+        .input
+        .as_deref()
+        .context("the text generator needs an input")?;
+    let text = render_text(
+        font,
+        content,
+        input.args.font_size,
+        input
+            .args
+            .text_color
+            .unwrap_or(RgbColor::new(255, 255, 255)),
+    );
+
+    Ok(Box::new(
+        input
+            .args
+            .source(DynamicImage::ImageRgba8(text), input.mask)?,
+    ))
+}
+
+fn fill_generator(input: &GeneratorInput) -> anyhow::Result<Box<dyn DynCommandGenerator>> {
+    let color: Color = input
+        .args
+        .input
+        .as_deref()
+        .context("the fill generator needs a colour as input")?
+        .parse()
+        .context("the fill generator expects a colour as rrggbb or rrggbbaa")?;
+    let (Some(width), Some(height)) = (input.args.width, input.args.height) else {
+        anyhow::bail!("the fill generator needs a width and a height");
+    };
+    let offset = Coordinates::new(input.args.offset_x, input.args.offset_y);
+
+    Ok(Box::new(Fill::new(offset, (width, height), color)))
+}
+
+/// Decodes the image if the job is drawn by the `image` generator, along with all of its frames if
+/// it is animated. The other generators don't read an image
+fn open_original(
+    image: Option<&Path>,
+    args: &ImageArgs,
+) -> anyhow::Result<(Option<DynamicImage>, Vec<Frame>)> {
+    if args.generator != IMAGE_GENERATOR {
+        return Ok((None, Vec::new()));
+    }
+
+    let input = image.context("the image generator needs the path of an image")?;
+    let mut frames = open_frames(input)
+        .with_context(|| format!("unable to load image from {}", input.display()))?;
+
+    // formats that can't be animated aren't decoded by `open_frames`, so only those are opened here
+    match frames.len() {
+        0 => Ok((Some(open_image(input)?), frames)),
+        1 => Ok((frames.pop().map(|frame| frame.image), Vec::new())),
+        _ => Ok((Some(frames[0].image.clone()), frames)),
+    }
+}
+
+/// Creates the generator `args` ask for
+fn create_source(
+    args: &ImageArgs,
+    original: Option<&DynamicImage>,
+    mask: Option<&DynamicImage>,
+    font: Option<&FontVec>,
+) -> anyhow::Result<Arc<dyn DynCommandGenerator>> {
+    let input = GeneratorInput {
+        args,
+        original,
+        mask,
+        font,
+    };
+
+    generators().create(&args.generator, &input).map(Arc::from)
+}
+
+/// Source of a job and those of all of its frames
+type Sources = (
+    Arc<dyn DynCommandGenerator>,
+    Vec<Arc<dyn DynCommandGenerator>>,
+);
+
+/// Creates the source of the job and those of all frames if it is animated, the first of which is
+/// the source itself
+fn create_sources(
+    args: &ImageArgs,
+    original: Option<&DynamicImage>,
+    frames: &[Frame],
+    mask: Option<&DynamicImage>,
+    font: Option<&FontVec>,
+) -> anyhow::Result<Sources> {
+    let frame_sources = frames
+        .iter()
+        .map(|frame| create_source(args, Some(&frame.image), mask, font))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let source = match frame_sources.first() {
+        Some(first) => first.clone(),
+        None => create_source(args, original, mask, font)?,
+    };
+
+    Ok((source, frame_sources))
+}
+
+/// Longest side of a preview, larger scenes are scaled down to fit
+const MAX_PREVIEW_SIZE: u32 = 1024;
+
+/// Draws the commands of a generator onto an image just covering the pixels they set, scaled down
+/// by a whole factor if it is larger than [`MAX_PREVIEW_SIZE`].
+///
+/// Returns the canvas position of the top left corner along with the image.
+fn preview(generator: &impl CommandGenerator) -> (Coordinates, RgbaImage) {
+    let pixels = generator
+        .commands()
+        .filter_map(|command| match command {
+            Command::SetPixel(command) => Some(command),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let Some((left, top, right, bottom)) =
+        pixels
+            .iter()
+            .fold(None::<(u32, u32, u32, u32)>, |bounds, command| {
+                let (x, y) = (command.coordinates.x, command.coordinates.y);
+                Some(match bounds {
+                    None => (x, y, x, y),
+                    Some((left, top, right, bottom)) => {
+                        (left.min(x), top.min(y), right.max(x), bottom.max(y))
+                    }
+                })
+            })
+    else {
+        return (Coordinates::new(0, 0), RgbaImage::new(0, 0));
+    };
+
+    let (width, height) = (right - left + 1, bottom - top + 1);
+    let scale = width.max(height).div_ceil(MAX_PREVIEW_SIZE);
+    let mut image = RgbaImage::new(width.div_ceil(scale), height.div_ceil(scale));
+    for command in pixels {
+        let color = match command.color {
+            Color::Rgb(rgb) => Rgba([rgb.r, rgb.g, rgb.b, u8::MAX]),
+            Color::Rgba(rgba) => Rgba([rgba.rgb.r, rgba.rgb.g, rgba.rgb.b, rgba.alpha]),
+        };
+        image.put_pixel(
+            (command.coordinates.x - left) / scale,
+            (command.coordinates.y - top) / scale,
+            color,
+        );
+    }
+
+    (Coordinates::new(left, top), image)
+}
+
+/// An image drawn onto a single target
+#[derive(Clone)]
+pub struct Job {
+    pub name: String,
+    pub target: Destination,
+    /// Path to the image of the `image` generator
+    pub image: Option<PathBuf>,
+    pub image_args: ImageArgs,
+    /// Decoded image before `image_args` are applied, only for the `image` generator
+    pub original: Option<DynamicImage>,
+    /// All frames of an animated image, the first of them being `original`. Empty for still images.
+    /// Shared, so copies of the job for rendering them are cheap
+    pub frames: Arc<[Frame]>,
+    /// Decoded `image_args.mask`
+    pub mask: Option<DynamicImage>,
+    /// Loaded `image_args.font`
+    pub font: Option<Arc<FontVec>>,
+    /// Regions of the canvas no shard writes into
+    pub exclusions: Exclusions,
+    pub source: Arc<dyn DynCommandGenerator>,
+    /// Sources of all `frames`, the first of them being `source`
+    pub frame_sources: Vec<Arc<dyn DynCommandGenerator>>,
+    /// What `source` draws, cropped to the pixels it sets and scaled down if it is large
+    pub preview: Arc<RgbaImage>,
+    /// Canvas position of the top left corner of `preview`
+    pub preview_offset: Coordinates,
+    pub shards: Vec<usize>,
+    pub num_shards: usize,
+    pub shard_strategy: ShardStrategy,
+    /// Relative share of every shard, empty for equal shares
+    pub shard_weights: Vec<u32>,
+}
+
+impl Job {
+    pub fn new(
+        name: String,
+        target: Destination,
+        image: Option<PathBuf>,
+        image_args: ImageArgs,
+        shards: Vec<usize>,
+        num_shards: usize,
+        shard_strategy: ShardStrategy,
+    ) -> anyhow::Result<Self> {
+        if num_shards == 0 {
+            anyhow::bail!("job {name}: num_shards has to be at least 1");
+        }
+        if let Some(shard) = shards.iter().find(|&&shard| shard >= num_shards) {
+            anyhow::bail!(
+                "job {name}: shard {shard} does not exist, there are {num_shards} shards"
+            );
+        }
+
+        let (original, frames) = open_original(image.as_deref(), &image_args)?;
+        let mask = image_args.mask.as_deref().map(open_image).transpose()?;
+        let font = image_args.font.as_deref().map(open_font).transpose()?;
+        let exclusions = image_args.exclusions()?;
+        let (source, frame_sources) = create_sources(
+            &image_args,
+            original.as_ref(),
+            &frames,
+            mask.as_ref(),
+            font.as_ref(),
+        )?;
+        let (preview_offset, preview) = preview(&source);
+
+        Ok(Self {
+            source,
+            frame_sources,
+            preview: Arc::new(preview),
+            preview_offset,
+            original,
+            frames: frames.into(),
+            mask,
+            font: font.map(Arc::new),
+            exclusions,
+            name,
+            target,
+            image,
+            image_args,
+            shards,
+            num_shards,
+            shard_strategy,
+            shard_weights: Vec::new(),
+        })
+    }
+
+    /// Loads the image, mask, font and exclusions from disk again, keeping the current source if that
+    /// fails.
+    pub fn reload(&mut self) -> anyhow::Result<()> {
+        let (original, frames) = open_original(self.image.as_deref(), &self.image_args)?;
+        let mask = self
+            .image_args
+            .mask
+            .as_deref()
+            .map(open_image)
+            .transpose()?;
+        let font = self.image_args.font.as_deref().map(open_font).transpose()?;
+        let exclusions = self.image_args.exclusions()?;
+        let (source, frame_sources) = create_sources(
+            &self.image_args,
+            original.as_ref(),
+            &frames,
+            mask.as_ref(),
+            font.as_ref(),
+        )?;
+
+        self.original = original;
+        self.frames = frames.into();
+        self.mask = mask;
+        self.font = font.map(Arc::new);
+        self.exclusions = exclusions;
+        self.set_sources(source, frame_sources);
+        Ok(())
+    }
+
+    /// Draws this still image instead, e.g. one that did not come from disk. Switches to the
+    /// `image` generator.
+    pub fn set_image(&mut self, image: DynamicImage) -> anyhow::Result<()> {
+        let mut args = self.image_args.clone();
+        args.generator = IMAGE_GENERATOR.to_string();
+        let source = create_source(&args, Some(&image), self.mask.as_ref(), None)?;
+
+        self.image_args = args;
+        // the upload isn't on disk, so nothing may reload or watch the previous path
+        self.image = None;
+        self.original = Some(image);
+        self.frames = Arc::from([]);
+        self.set_sources(source, Vec::new());
+        Ok(())
+    }
+
+    /// Draws the image at `path` instead, switching to the `image` generator. Keeps the current
+    /// image and source if it can't be loaded.
+    pub fn select_image(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        let image = self.image.replace(path);
+        let generator = std::mem::replace(&mut self.image_args.generator, IMAGE_GENERATOR.into());
+
+        let result = self.reload();
+        if result.is_err() {
+            self.image = image;
+            self.image_args.generator = generator;
+        }
+
+        result
+    }
+
+    /// Applies changed `image_args` to the current input, keeping the current source if that fails.
+    pub fn rebuild(&mut self) -> anyhow::Result<()> {
+        let (source, frame_sources) = create_sources(
+            &self.image_args,
+            self.original.as_ref(),
+            &self.frames,
+            self.mask.as_ref(),
+            self.font.as_deref(),
+        )?;
+
+        self.set_sources(source, frame_sources);
+        Ok(())
+    }
+
+    fn set_sources(
+        &mut self,
+        source: Arc<dyn DynCommandGenerator>,
+        frame_sources: Vec<Arc<dyn DynCommandGenerator>>,
+    ) {
+        let (offset, preview) = preview(&source);
+        self.source = source;
+        self.frame_sources = frame_sources;
+        self.preview = Arc::new(preview);
+        self.preview_offset = offset;
+    }
+
+    /// Sets the relative share of every shard, one weight per shard or none for equal shares.
+    pub fn set_shard_weights(&mut self, weights: Vec<u32>) -> anyhow::Result<()> {
+        if !weights.is_empty() && weights.len() != self.num_shards {
+            anyhow::bail!(
+                "expected {} shard weights, got {}",
+                self.num_shards,
+                weights.len()
+            );
+        }
+
+        self.shard_weights = weights;
+        Ok(())
+    }
+
+    /// Distributes the shards of this job round-robin across the given number of connections.
+    pub fn distribute(&self, connections: usize) -> Vec<Vec<usize>> {
+        distribute(&self.shards, connections)
+    }
+
+    /// A source of the job with the exclusions applied.
+    ///
+    /// Excluded before sharding, so the remaining pixels are still split evenly.
+    fn excluded(
+        &self,
+        source: &Arc<dyn DynCommandGenerator>,
+    ) -> Exclude<Arc<dyn DynCommandGenerator>> {
+        Exclude::new(source.clone(), &self.exclusions)
+    }
+
+    /// Renders the commands of a single shard.
+    pub fn render_shard(&self, shard: usize) -> Arc<[u8]> {
+        let source = self.excluded(&self.source);
+        let layout = self.layout(&source);
+
+        self.render(&source, &layout, shard)
+    }
+
+    /// Which commands of a source belong to which shard, shared by all shards of a render
+    fn layout(&self, source: &Exclude<Arc<dyn DynCommandGenerator>>) -> Arc<Layout> {
+        Arc::new(Layout::new(
+            self.shard_strategy,
+            source,
+            self.num_shards,
+            &self.shard_weights,
+        ))
+    }
+
+    fn render(
+        &self,
+        source: &Exclude<Arc<dyn DynCommandGenerator>>,
+        layout: &Arc<Layout>,
+        shard: usize,
+    ) -> Arc<[u8]> {
+        let mut builder = PayloadBuilder::new().line_ending(self.image_args.line_ending);
+        builder.extend(
+            Shard::new(source, shard, self.num_shards)
+                .layout(layout.clone())
+                .commands(),
+        );
+
+        builder.build()
+    }
+
+    /// Renders shards on all cores, handing each one to `ready` on the calling thread as soon as it
+    /// is done, so it can be sent while the others are still rendering.
+    pub fn render_shards(&self, shards: &[usize], ready: impl FnMut(usize, Arc<[u8]>)) {
+        self.render_shards_of(&self.source, shards, ready);
+    }
+
+    fn render_shards_of(
+        &self,
+        source: &Arc<dyn DynCommandGenerator>,
+        shards: &[usize],
+        mut ready: impl FnMut(usize, Arc<[u8]>),
+    ) {
+        let workers = std::thread::available_parallelism()
+            .map_or(1, NonZero::get)
+            .min(shards.len());
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        let source = self.excluded(source);
+        let layout = self.layout(&source);
+
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                let (sender, next, source, layout) = (sender.clone(), &next, &source, &layout);
+
+                scope.spawn(move || {
+                    while let Some(&shard) = shards.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        if sender
+                            .send((shard, self.render(source, layout, shard)))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for (shard, payload) in receiver {
+                ready(shard, payload);
+            }
+        });
+    }
+
+    /// Renders all shards of the job in parallel.
+    pub fn render_all(&self) -> HashMap<usize, Arc<[u8]>> {
+        self.render_all_of(&self.source)
+    }
+
+    fn render_all_of(&self, source: &Arc<dyn DynCommandGenerator>) -> HashMap<usize, Arc<[u8]>> {
+        let mut rendered = HashMap::new();
+        self.render_shards_of(source, &self.shards, |shard, payload| {
+            rendered.insert(shard, payload);
+        });
+
+        rendered
+    }
+
+    /// Renders all shards of every frame of an animated image, empty for still images.
+    pub fn render_frames(&self) -> Vec<RenderedFrame> {
+        self.frame_sources
+            .iter()
+            .zip(self.frames.iter())
+            .map(|(source, frame)| RenderedFrame {
+                shards: self.render_all_of(source),
+                delay: frame.delay,
+            })
+            .collect()
+    }
+}
+
+/// Rendered shards of a frame of an animation
+pub struct RenderedFrame {
+    pub shards: HashMap<usize, Arc<[u8]>>,
+    /// How long the frame is shown before the next one
+    pub delay: Duration,
+}
+
+/// Puts together the payload of a connection from already rendered shards, sharing their buffers.
+pub fn assemble(rendered: &HashMap<usize, Arc<[u8]>>, shards: &[usize]) -> Payload {
+    Payload::new(shards.iter().map(|shard| rendered[shard].clone()).collect())
+}
+
+fn distribute(shards: &[usize], connections: usize) -> Vec<Vec<usize>> {
+    let count = connections.clamp(1, shards.len().max(1));
+    let mut groups = vec![Vec::new(); count];
+
+    for (index, &shard) in shards.iter().enumerate() {
+        groups[index % count].push(shard);
+    }
+
+    groups
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+#[error("a budget of {budget} connections is not enough to run {jobs} jobs")]
+pub struct InsufficientBudgetError {
+    pub budget: usize,
+    pub jobs: usize,
+}
+
+/// Splits a budget of connections between jobs requesting the given number of connections each.
+///
+/// Connections are handed out round-robin, so every job gets at least one connection and no job
+/// gets more than it asked for. Without a budget every job gets what it asked for.
+pub fn allocate_connections(
+    requested: &[usize],
+    budget: Option<usize>,
+) -> Result<Vec<usize>, InsufficientBudgetError> {
+    let Some(mut budget) = budget else {
+        return Ok(requested.to_vec());
+    };
+
+    // jobs written to outputs don't connect at all
+    let jobs = requested.iter().filter(|&&requested| requested > 0).count();
+    if budget < jobs {
+        return Err(InsufficientBudgetError { budget, jobs });
+    }
+
+    let mut allocated = vec![0; requested.len()];
+
+    while budget > 0 {
+        let mut progress = false;
+
+        for (allocated, &requested) in allocated.iter_mut().zip(requested) {
+            if budget > 0 && *allocated < requested {
+                *allocated += 1;
+                budget -= 1;
+                progress = true;
+            }
+        }
+
+        if !progress {
+            break;
+        }
+    }
+
+    Ok(allocated)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_generator::animation::Frame;
+    use crate::command_generator::exclude::Exclusions;
+    use crate::command_generator::shard::ShardStrategy;
+    use crate::dump::{Destination, Output};
+    use crate::job::{allocate_connections, distribute, ImageArgs, InsufficientBudgetError, Job};
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use schwitzerflut_protocol::coordinates::Coordinates;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn job(shards: Vec<usize>, num_shards: usize) -> Job {
+        let original =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])));
+
+        let mut job = Job {
+            name: "test".into(),
+            target: Destination::Output(Output::Stdout),
+            image: None,
+            image_args: ImageArgs::default(),
+            source: Arc::new(ImageArgs::default().source(original.clone(), None).unwrap()),
+            preview: Arc::new(RgbaImage::new(0, 0)),
+            preview_offset: Coordinates::new(0, 0),
+            original: None,
+            frames: Arc::from([]),
+            frame_sources: Vec::new(),
+            mask: None,
+            font: None,
+            exclusions: Exclusions::default(),
+            shards,
+            num_shards,
+            shard_strategy: ShardStrategy::Modulus,
+            shard_weights: Vec::new(),
+        };
+        job.set_image(original).unwrap();
+
+        job
+    }
+
+    #[test]
+    fn test_reject_invalid_shards() {
+        let new = |shards: Vec<usize>, num_shards: usize| {
+            Job::new(
+                "test".into(),
+                Destination::Output(Output::Stdout),
+                Some(PathBuf::from("missing.png")),
+                ImageArgs::default(),
+                shards,
+                num_shards,
+                ShardStrategy::Modulus,
+            )
+            .err()
+            .unwrap()
+            .to_string()
+        };
+
+        assert_eq!(
+            new(vec![1], 1),
+            "job test: shard 1 does not exist, there are 1 shards"
+        );
+        assert_eq!(new(vec![0], 0), "job test: num_shards has to be at least 1");
+    }
+
+    #[test]
+    fn test_render_all_shards() {
+        let job = job(vec![0, 1, 2], 3);
+
+        let rendered = job.render_all();
+
+        assert_eq!(rendered.len(), 3);
+        for shard in 0..3 {
+            assert_eq!(rendered[&shard], job.render_shard(shard));
+        }
+        let lines = rendered
+            .values()
+            .map(|payload| payload.split(|&b| b == b'\n').count() - 1);
+        assert_eq!(lines.sum::<usize>(), 16);
+    }
+
+    #[test]
+    fn test_exclusions_apply_after_offset() {
+        let mut job = job(vec![0, 1], 2);
+        job.image_args.offset_x = 10;
+        job.image_args.offset_y = 10;
+        job.rebuild().unwrap();
+        job.exclusions = Exclusions(vec!["2x2+10+10".parse().unwrap()]);
+
+        let rendered = job.render_all();
+        let commands = rendered
+            .values()
+            .flat_map(|payload| std::str::from_utf8(payload).unwrap().lines())
+            .collect::<Vec<_>>();
+
+        assert_eq!(commands.len(), 12);
+        assert!(!commands
+            .iter()
+            .any(|command| command.starts_with("PX 10 10 ")));
+        assert!(commands
+            .iter()
+            .any(|command| command.starts_with("PX 12 10 ")));
+        // the remaining pixels are still split evenly
+        assert_eq!(rendered[&0].len(), rendered[&1].len());
+    }
+
+    #[test]
+    fn test_shard_weights() {
+        let mut job = job(vec![0, 1], 2);
+        assert!(job.set_shard_weights(vec![1, 2, 3]).is_err());
+
+        job.set_shard_weights(vec![3, 1]).unwrap();
+        let rendered = job.render_all();
+        let lines = |shard: usize| rendered[&shard].split(|&b| b == b'\n').count() - 1;
+
+        assert_eq!((lines(0), lines(1)), (12, 4));
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut job = job(vec![0], 1);
+        job.image_args.offset_x = 3;
+        job.rebuild().unwrap();
+
+        assert_eq!(job.preview_offset, Coordinates::new(3, 0));
+        assert_eq!(job.preview.dimensions(), (4, 4));
+        assert_eq!(job.preview.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_size_needs_both_dimensions_without_fit() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        let mut args = ImageArgs {
+            width: Some(2),
+            ..ImageArgs::default()
+        };
+
+        assert!(args.source(image.clone(), None).is_err());
+
+        args.fit = true;
+        assert!(args.source(image.clone(), None).is_ok());
+
+        args.fit = false;
+        args.height = Some(2);
+        assert!(args.source(image, None).is_ok());
+    }
+
+    #[test]
+    fn test_reload_keeps_uploaded_image() {
+        let path = std::env::temp_dir().join(format!("job-upload-{}.png", std::process::id()));
+        let red = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        red.save(&path).unwrap();
+
+        let mut job = Job::new(
+            "test".into(),
+            Destination::Output(Output::Stdout),
+            Some(path.clone()),
+            ImageArgs::default(),
+            vec![0],
+            1,
+            ShardStrategy::Modulus,
+        )
+        .unwrap();
+        let blue = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 255, 255]));
+        job.set_image(DynamicImage::ImageRgba8(blue)).unwrap();
+
+        RgbaImage::from_pixel(2, 2, Rgba([0, 255, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let reloaded = job.reload();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(job.image, None);
+        assert!(reloaded.is_err());
+        assert!(std::str::from_utf8(&job.render_shard(0))
+            .unwrap()
+            .lines()
+            .all(|command| command.ends_with("0000ffff")));
+    }
+
+    #[test]
+    fn test_fill_generator() {
+        let mut job = job(vec![0], 1);
+        job.image_args.input = Some("00ff00".into());
+        job.image_args.generator = "fill".into();
+        job.image_args.offset_x = 5;
+        job.image_args.width = Some(2);
+        job.image_args.height = Some(3);
+        job.rebuild().unwrap();
+
+        let rendered = job.render_shard(0);
+        let commands = std::str::from_utf8(&rendered).unwrap().lines();
+
+        assert_eq!(commands.clone().count(), 6);
+        assert!(commands.clone().all(|command| command.ends_with(" 00ff00")));
+        assert_eq!(job.preview_offset, Coordinates::new(5, 0));
+        assert_eq!(job.preview.dimensions(), (2, 3));
+    }
+
+    #[test]
+    fn test_preview_scales_down_large_scenes() {
+        let mut job = job(vec![0], 1);
+        job.image_args.generator = "fill".into();
+        job.image_args.input = Some("00ff00".into());
+        job.image_args.width = Some(5000);
+        job.image_args.height = Some(2);
+        job.rebuild().unwrap();
+
+        assert_eq!(job.preview.dimensions(), (1000, 1));
+        assert_eq!(job.preview.get_pixel(999, 0), &Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_generator_errors_keep_the_source() {
+        let mut job = job(vec![0], 1);
+
+        job.image_args.generator = "fill".into();
+        assert!(job.rebuild().is_err());
+
+        job.image_args.generator = "text".into();
+        assert!(job.rebuild().is_err());
+
+        job.image_args.generator = "video".into();
+        let error = job.rebuild().unwrap_err();
+        assert!(error.to_string().contains("fill, image, text"));
+
+        assert_eq!(job.render_shard(0).split(|&b| b == b'\n').count() - 1, 16);
+    }
+
+    #[test]
+    fn test_render_frames() {
+        let mut job = job(vec![0, 1], 2);
+        assert!(job.render_frames().is_empty());
+
+        job.frames = [(Rgba([0, 0, 255, 255]), 40), (Rgba([0, 255, 0, 255]), 80)]
+            .into_iter()
+            .map(|(color, delay)| Frame {
+                image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, color)),
+                delay: Duration::from_millis(delay),
+            })
+            .collect();
+        job.original = Some(job.frames[0].image.clone());
+        job.rebuild().unwrap();
+
+        let frames = job.render_frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].delay, Duration::from_millis(80));
+        for (frame, color) in frames.iter().zip(["0000ffff", "00ff00ff"]) {
+            assert_eq!(frame.shards.len(), 2);
+            assert!(std::str::from_utf8(&frame.shards[&1])
+                .unwrap()
+                .lines()
+                .all(|command| command.ends_with(color)));
+        }
+        // the first frame is what is drawn before the animation starts
+        assert_eq!(frames[0].shards, job.render_all());
+
+        job.set_image(DynamicImage::ImageRgba8(RgbaImage::new(1, 1)))
+            .unwrap();
+        assert!(job.render_frames().is_empty());
+    }
+
+    #[test]
+    fn test_allocate_without_budget() {
+        assert_eq!(allocate_connections(&[4, 2], None), Ok(vec![4, 2]))
+    }
+
+    #[test]
+    fn test_allocate_round_robin() {
+        assert_eq!(allocate_connections(&[4, 1, 3], Some(6)), Ok(vec![3, 1, 2]))
+    }
+
+    #[test]
+    fn test_allocate_budget_larger_than_requested() {
+        assert_eq!(allocate_connections(&[2, 1], Some(10)), Ok(vec![2, 1]))
+    }
+
+    #[test]
+    fn test_allocate_insufficient_budget() {
+        assert_eq!(
+            allocate_connections(&[2, 1, 1], Some(2)),
+            Err(InsufficientBudgetError { budget: 2, jobs: 3 })
+        )
+    }
+
+    #[test]
+    fn test_allocate_ignores_outputs() {
+        assert_eq!(allocate_connections(&[0, 2, 0], Some(1)), Ok(vec![0, 1, 0]))
+    }
+
+    #[test]
+    fn test_distribute_shards() {
+        assert_eq!(
+            distribute(&[0, 1, 2, 3, 4], 2),
+            vec![vec![0, 2, 4], vec![1, 3]]
+        )
+    }
+}
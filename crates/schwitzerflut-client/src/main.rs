@@ -1,9 +1,16 @@
 #![allow(unused)]
 
+use crate::cancel::CancellationToken;
 use crate::command_generator::image::ImageSourceBuilder;
-use crate::command_generator::shard::Shard;
+use crate::command_generator::shard::{Shard, ShardStrategy};
 use crate::command_generator::CommandGenerator;
-use crate::stream::StreamWrapper;
+use crate::config::JobFile;
+use crate::control::ControlServer;
+use crate::dump::{check_outputs, Destination};
+use crate::job::{allocate_connections, ImageArgs, Job};
+use crate::output::info;
+use crate::runner::{RunningJob, SendOptions};
+use crate::stream::transport::Target;
 use anyhow::Context;
 use clap::Parser;
 use image::DynamicImage;
@@ -13,94 +20,236 @@ use std::error::Error;
 use std::fmt::format;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+mod cancel;
 mod command_generator;
+mod config;
+mod control;
+mod dump;
+mod job;
+mod output;
+mod runner;
 mod stream;
+mod tui;
+mod watch;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    #[arg(env)]
-    address: SocketAddr,
+    /// Address of the pixelflut server, either `<ip>:<port>` or `unix:<path>`.
+    /// Use `-` or `file:<path>` to write the commands to stdout or a file instead, `{shard}` in the
+    /// path writes one file per shard
+    #[arg(env, required_unless_present = "config")]
+    address: Option<Destination>,
 
-    /// Path to the image to display
-    #[arg(env)]
-    image: PathBuf,
+    /// Path to the image to display with the `image` generator
+    #[arg(env, required_unless_present_any = ["config", "input"])]
+    image: Option<PathBuf>,
 
-    #[arg(long, env, default_value_t = 0)]
-    offset_x: u32,
-    #[arg(long, env, default_value_t = 0)]
-    offset_y: u32,
+    /// Run the jobs declared in a TOML job file instead of drawing a single image
+    #[arg(long, env, conflicts_with_all = ["address", "image"])]
+    config: Option<PathBuf>,
 
-    #[arg(long, env, required = false)]
-    height: Option<u32>,
-
-    #[arg(long, env, required = false)]
-    width: Option<u32>,
-
-    /// Whether to send set pixel commands for transparent pixels
-    #[arg(long, env, default_value_t = true)]
-    skip_transparent_pixels: bool,
+    #[command(flatten)]
+    image_args: ImageArgs,
 
     /// Shards to handle with this client. If there is more than one connection configured,
     /// then shards are distributed across them
-    #[arg(long, env, default_values_t = [1], value_delimiter=',')]
+    #[arg(long, env, default_values_t = [0], value_delimiter=',')]
     shards: Vec<usize>,
 
     /// Total number of shards
     #[arg(long, env, default_value_t = 1)]
     num_shards: usize,
+
+    /// How the pixels are split between shards
+    #[arg(long, env, value_enum, default_value_t = ShardStrategy::Modulus)]
+    shard_strategy: ShardStrategy,
+
+    /// Relative share of every shard, e.g. `4,1,1` to give the first of three shards two thirds
+    /// of the pixels
+    #[arg(long, env, value_delimiter = ',')]
+    shard_weights: Vec<u32>,
+
+    /// Stop after running for this long, e.g. `90s` or `1h 30m`
+    #[arg(long, env, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+
+    /// Stop after every shard has sent its payload this many times. Outputs are written once by
+    /// default
+    #[arg(long, env)]
+    loops: Option<u64>,
+
+    /// Reload images when they change on disk, without reconnecting
+    #[arg(long, env)]
+    watch: bool,
+
+    /// Every this often, move pixels from slow connections to fast ones based on their
+    /// measured throughput, e.g. `30s`
+    #[arg(long, env, value_parser = humantime::parse_duration)]
+    rebalance: Option<Duration>,
+
+    /// Maximum throughput of each connection in bytes per second
+    #[arg(long, env)]
+    rate_limit: Option<u64>,
+
+    /// Serve the HTTP control API on `<ip>:<port>` or `unix:<path>`
+    #[arg(long, env)]
+    control: Option<Target>,
+
+    /// Directory the control API may select images from by path. Without it, images can only be
+    /// uploaded
+    #[arg(long, env, requires = "control")]
+    control_image_dir: Option<PathBuf>,
+
+    /// Show an interactive dashboard instead of log output
+    #[arg(long, env)]
+    tui: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
 
-    let source = {
-        let image = image::open(&args.image)
-            .with_context(|| format!("unable to load image from {}", &args.image.display()))?;
+    let (jobs, max_connections) = match &args.config {
+        Some(path) => {
+            let file = JobFile::load(path)?;
+            let max_connections = file.max_connections;
+            let base_dir = path.parent().unwrap_or(Path::new("."));
+
+            (file.into_jobs(base_dir)?, max_connections)
+        }
+        None => {
+            let Some(address) = args.address.clone() else {
+                unreachable!("address is required without a job file");
+            };
+            let name = match &args.image {
+                Some(image) => image.display().to_string(),
+                None => args.image_args.generator.clone(),
+            };
+
+            let mut job = Job::new(
+                name,
+                address,
+                args.image.clone(),
+                args.image_args.clone(),
+                args.shards.clone(),
+                args.num_shards,
+                args.shard_strategy,
+            )?;
+            job.set_shard_weights(args.shard_weights.clone())?;
+
+            (vec![job], None)
+        }
+    };
 
-        let mut builder = ImageSourceBuilder::new(image)
-            .offset(Coordinates::new(args.offset_x, args.offset_y))
-            .include_transparent_pixels(!args.skip_transparent_pixels);
+    let requested = jobs
+        .iter()
+        .map(|job| match job.target {
+            Destination::Output(_) => 0,
+            Destination::Server(_) => job.shards.len(),
+        })
+        .collect::<Vec<_>>();
+    let connections = allocate_connections(&requested, max_connections)?;
+    check_outputs(jobs.iter().filter_map(|job| match &job.target {
+        Destination::Output(output) => Some(output),
+        Destination::Server(_) => None,
+    }))?;
+
+    let cancellation = CancellationToken::default();
+    cancellation
+        .cancel_on_signals()
+        .context("unable to register signal handlers")?;
+
+    if let Some(duration) = args.duration {
+        cancellation.cancel_after(duration);
+    }
 
-        if let (Some(x), Some(y)) = (args.width, args.height) {
-            builder = builder.resize((x, y));
-        };
+    if args.tui {
+        output::capture();
+    }
 
-        builder.build()
+    let started = Instant::now();
+    let options = SendOptions {
+        loops: args.loops,
+        rate_limit: args.rate_limit,
     };
 
+    let mut running = Vec::new();
     let mut handles = Vec::new();
 
-    for n in args.shards {
-        let stream = match StreamWrapper::new(args.address).connect() {
-            Ok(stream) => {
-                println!("shard {} connected successfully", n);
-                stream
-            }
-            Err(e) => {
-                eprintln!("shard {} failed to connect: {}", n, e);
+    for (index, (job, connections)) in jobs.into_iter().zip(connections).enumerate() {
+        let target = match &job.target {
+            Destination::Output(output) => {
+                output.write(&job, index, args.loops.unwrap_or(1), &cancellation)?;
                 continue;
             }
+            Destination::Server(target) => target.clone(),
         };
 
-        let shard = Shard::new(source.clone(), n, args.num_shards);
-        let payload = shard
-            .commands()
-            .map(|command| command.to_string())
-            .collect::<Vec<String>>()
-            .join("\n");
+        let (job, job_handles) =
+            RunningJob::start(job, target, connections, options, &cancellation);
+
+        if args.watch {
+            job.watch(cancellation.clone());
+        }
 
-        handles.push(std::thread::spawn(move || {
-            stream.send(payload);
+        if let Some(interval) = args.rebalance {
+            job.rebalance(interval, cancellation.clone());
+        }
 
-            println!("shard {} disconnected", n)
-        }));
+        running.push(job);
+        handles.push(job_handles);
     }
 
-    for handle in handles {
-        let _ = handle.join();
+    if let Some(listen) = &args.control {
+        ControlServer::bind(listen, running.clone())?
+            .image_dir(args.control_image_dir.clone())
+            .spawn(cancellation.clone());
+        info!("control endpoint listening on {}", listen);
     }
 
+    if args.tui {
+        let result = tui::run(&running, &cancellation);
+        cancellation.cancel();
+
+        for line in output::release() {
+            println!("{}", line);
+        }
+
+        result.context("unable to run the dashboard")?;
+    }
+
+    let mut total_bytes = 0;
+
+    for (job, handles) in running.iter().zip(handles) {
+        for (connection, handle) in job.connections().iter().zip(handles) {
+            let _ = handle.join();
+
+            println!(
+                "{}: sent {} bytes in {} full payloads, {} reconnects",
+                connection.label,
+                connection.stats.bytes_sent(),
+                connection.stats.payloads_sent(),
+                connection.stats.reconnects()
+            );
+
+            total_bytes += connection.stats.bytes_sent();
+        }
+    }
+
+    // nothing was sent, e.g. because every job was written to an output
+    if running.is_empty() {
+        return Ok(());
+    }
+
+    let elapsed = started.elapsed();
+    println!(
+        "sent {} bytes in {:.1}s ({:.2} MiB/s)",
+        total_bytes,
+        elapsed.as_secs_f64(),
+        total_bytes as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+
     Ok(())
 }
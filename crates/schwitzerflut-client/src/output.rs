@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Number of lines kept while output is captured
+const CAPTURED_LINES: usize = 200;
+
+/// Captured lines, `None` while output goes straight to the terminal
+static CAPTURED: Mutex<Option<VecDeque<String>>> = Mutex::new(None);
+
+/// Writes a status line to stdout, or to the capture buffer while output is captured
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::output::write(format!($($arg)*), false)
+    };
+}
+
+/// Writes an error line to stderr, or to the capture buffer while output is captured
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::output::write(format!($($arg)*), true)
+    };
+}
+
+pub(crate) use {error, info};
+
+pub fn write(line: String, error: bool) {
+    let mut captured = CAPTURED.lock().unwrap();
+
+    match captured.as_mut() {
+        Some(lines) => {
+            if lines.len() == CAPTURED_LINES {
+                lines.pop_front();
+            }
+
+            lines.push_back(line);
+        }
+        None if error => eprintln!("{}", line),
+        None => println!("{}", line),
+    }
+}
+
+/// Keeps output from other threads away from the terminal, e.g. while it is drawn on.
+pub fn capture() {
+    CAPTURED.lock().unwrap().get_or_insert_with(VecDeque::new);
+}
+
+/// Stops capturing output and returns the lines captured so far.
+pub fn release() -> Vec<String> {
+    CAPTURED
+        .lock()
+        .unwrap()
+        .take()
+        .map(Vec::from)
+        .unwrap_or_default()
+}
+
+/// The most recent captured lines, oldest first
+pub fn recent(count: usize) -> Vec<String> {
+    CAPTURED
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|lines| lines.iter().rev().take(count).rev().cloned().collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,637 @@
+use crate::cancel::CancellationToken;
+use crate::job::{assemble, Job, IMAGE_GENERATOR};
+use crate::output::{error, info};
+use crate::stream::payload::{Payload, SharedPayload};
+use crate::stream::stats::Stats;
+use crate::stream::throttle::Throttle;
+use crate::stream::transport::Target;
+use crate::stream::StreamWrapper;
+use crate::watch::FileWatcher;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How often to check images for modifications when watching them
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Smallest weight a connection keeps when rebalancing, relative to the fastest one, so slow
+/// connections keep sending and their throughput can still be measured
+const MIN_REBALANCE_SHARE: f64 = 0.05;
+
+/// Weight of the shards of the fastest connection after rebalancing
+const REBALANCE_SCALE: f64 = 1000.0;
+
+/// Smallest change in the share of any shard that is worth rendering again for
+const REBALANCE_THRESHOLD: f64 = 0.02;
+
+/// Settings applied to every connection of a job
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SendOptions {
+    pub loops: Option<u64>,
+    /// Initial rate limit of each connection in bytes per second
+    pub rate_limit: Option<u64>,
+}
+
+/// Handles to a connection sending on its own thread
+pub struct Connection {
+    pub label: String,
+    pub shards: Vec<usize>,
+    pub payload: SharedPayload,
+    pub stats: Arc<Stats>,
+    pub throttle: Arc<Throttle>,
+}
+
+/// A job whose connections are sending. Changes to the job are rendered and swapped into all of its
+/// connections without reconnecting.
+pub struct RunningJob {
+    job: Mutex<Job>,
+    connections: Vec<Connection>,
+    /// Bumped by every update, so the animation knows when to render its frames again
+    revision: AtomicU64,
+    /// Whether a thread is cycling through the frames of an animated image
+    animating: Mutex<bool>,
+    /// Offset not yet applied by the thread moving the image, `None` if no such thread is running
+    moving: Mutex<Option<(i32, i32)>>,
+    /// Stops the animation
+    cancellation: CancellationToken,
+}
+
+impl RunningJob {
+    /// Opens the given number of connections for a job and starts sending on each of them.
+    ///
+    /// Shards are rendered in parallel, and every connection starts sending as soon as all of its
+    /// shards are rendered. Returns the join handles of the sending threads in the same order as
+    /// [`connections`](Self::connections).
+    pub fn start(
+        job: Job,
+        target: Target,
+        connections: usize,
+        options: SendOptions,
+        cancellation: &CancellationToken,
+    ) -> (Arc<Self>, Vec<JoinHandle<()>>) {
+        let groups = job.distribute(connections);
+        let mut started = groups.iter().map(|_| None).collect::<Vec<_>>();
+        let mut rendered = HashMap::new();
+
+        job.render_shards(&job.shards, |shard, payload| {
+            rendered.insert(shard, payload);
+
+            for (index, shards) in groups.iter().enumerate() {
+                if shards.contains(&shard) && shards.iter().all(|s| rendered.contains_key(s)) {
+                    let payload = assemble(&rendered, shards);
+                    started[index] = open(&job, &target, shards, payload, options, cancellation);
+                }
+            }
+        });
+
+        let (running, handles) = started.into_iter().flatten().unzip();
+        let animated = !job.frames.is_empty();
+        let job = Arc::new(Self {
+            job: Mutex::new(job),
+            connections: running,
+            revision: AtomicU64::new(0),
+            animating: Mutex::new(false),
+            moving: Mutex::new(None),
+            cancellation: cancellation.clone(),
+        });
+
+        if animated {
+            job.animate();
+        }
+
+        (job, handles)
+    }
+
+    pub fn name(&self) -> String {
+        self.job.lock().unwrap().name.clone()
+    }
+
+    pub fn connections(&self) -> &[Connection] {
+        &self.connections
+    }
+
+    /// Reads the current state of the job
+    pub fn inspect<R>(&self, f: impl FnOnce(&Job) -> R) -> R {
+        f(&self.job.lock().unwrap())
+    }
+
+    /// Modifies the job and swaps the re-rendered payloads into all connections.
+    ///
+    /// The frames of an animated image are rendered again by the animation afterwards, so the job
+    /// isn't locked for that long. If `f` fails, the payloads are left untouched.
+    pub fn update<R>(
+        self: &Arc<Self>,
+        f: impl FnOnce(&mut Job) -> anyhow::Result<R>,
+    ) -> anyhow::Result<R> {
+        let mut job = self.job.lock().unwrap();
+        let result = f(&mut job)?;
+        self.store(&job.render_all());
+        self.revision.fetch_add(1, Ordering::Release);
+        let animated = !job.frames.is_empty();
+        drop(job);
+
+        if animated {
+            self.animate();
+        }
+
+        Ok(result)
+    }
+
+    /// Swaps rendered shards into all connections
+    fn store(&self, rendered: &HashMap<usize, Arc<[u8]>>) {
+        for connection in &self.connections {
+            connection
+                .payload
+                .store(assemble(rendered, &connection.shards));
+        }
+    }
+
+    /// Cycles through the frames of an animated image, unless that is already happening.
+    ///
+    /// The payloads of all connections are switched at once, so their shards stay roughly in step.
+    /// Connections pick up the next frame after finishing the payload they are sending. Stops once
+    /// the job is no longer animated.
+    fn animate(self: &Arc<Self>) {
+        let mut animating = self.animating.lock().unwrap();
+        if *animating {
+            return;
+        }
+        *animating = true;
+
+        let running = self.clone();
+        std::thread::spawn(move || running.cycle_frames());
+    }
+
+    fn cycle_frames(&self) {
+        let mut frames = Vec::new();
+        let mut rendered = None;
+        let mut index = 0;
+
+        loop {
+            let revision = self.revision.load(Ordering::Acquire);
+            if rendered != Some(revision) {
+                // rendered from a copy, so the job stays unlocked meanwhile
+                let job = self.inspect(Job::clone);
+                frames = job.render_frames();
+                rendered = Some(revision);
+                index = 0;
+            }
+
+            let Some(frame) = frames.get(index) else {
+                let mut animating = self.animating.lock().unwrap();
+                // an update might have brought back an animation in the meantime
+                if self.revision.load(Ordering::Acquire) == revision {
+                    *animating = false;
+                    return;
+                }
+                continue;
+            };
+
+            {
+                // checked under the lock, so a frame never replaces what an update just stored
+                let _job = self.job.lock().unwrap();
+                if self.revision.load(Ordering::Acquire) != revision {
+                    continue;
+                }
+                self.store(&frame.shards);
+            }
+
+            if !self.cancellation.sleep(frame.delay) {
+                return;
+            }
+            index = (index + 1) % frames.len();
+        }
+    }
+
+    /// Moves the image by the given offset on a separate thread.
+    ///
+    /// Moves requested while the image is rendered are combined into a single one, so holding a
+    /// key doesn't queue up a render for every repeat.
+    pub fn nudge(self: &Arc<Self>, dx: i32, dy: i32) {
+        let mut moving = self.moving.lock().unwrap();
+        if let Some((x, y)) = moving.as_mut() {
+            *x = x.saturating_add(dx);
+            *y = y.saturating_add(dy);
+            return;
+        }
+        *moving = Some((dx, dy));
+
+        let running = self.clone();
+        std::thread::spawn(move || running.apply_moves());
+    }
+
+    fn apply_moves(self: &Arc<Self>) {
+        loop {
+            let (dx, dy) = {
+                let mut moving = self.moving.lock().unwrap();
+                match *moving {
+                    Some(offset) if offset != (0, 0) => {
+                        *moving = Some((0, 0));
+                        offset
+                    }
+                    _ => {
+                        *moving = None;
+                        return;
+                    }
+                }
+            };
+
+            let result = self.update(|job| {
+                job.image_args.offset_x = job.image_args.offset_x.saturating_add_signed(dx);
+                job.image_args.offset_y = job.image_args.offset_y.saturating_add_signed(dy);
+                job.rebuild()
+            });
+            if let Err(e) = result {
+                error!("{}: unable to move image: {:#}", self.name(), e);
+            }
+        }
+    }
+
+    /// Moves pixels from slow connections to fast ones every `interval`.
+    ///
+    /// The weights of the shards of every connection are scaled by how often it got through its
+    /// payload since the last check, until all connections take equally long for a pass.
+    ///
+    /// Only done if this client draws all shards of the job, as other clients would not know
+    /// about the new weights.
+    pub fn rebalance(self: &Arc<Self>, interval: Duration, cancellation: CancellationToken) {
+        let running = self.clone();
+        let num_shards = running.inspect(|job| job.num_shards);
+
+        let mut drawn = running
+            .connections
+            .iter()
+            .flat_map(|connection| connection.shards.iter().copied())
+            .collect::<Vec<_>>();
+        drawn.sort_unstable();
+        if drawn != (0..num_shards).collect::<Vec<_>>() {
+            error!(
+                "{}: not rebalancing, not all shards are drawn by this client",
+                running.name()
+            );
+            return;
+        }
+
+        std::thread::spawn(move || {
+            let groups = running
+                .connections
+                .iter()
+                .map(|connection| connection.shards.as_slice())
+                .collect::<Vec<_>>();
+            let mut sent = running.bytes_sent();
+
+            while cancellation.sleep(interval) {
+                let now = running.bytes_sent();
+                let passes = running
+                    .connections
+                    .iter()
+                    .zip(now.iter().zip(&sent))
+                    .map(|(connection, (now, before))| {
+                        let length = connection.payload.load().len().max(1);
+                        now.saturating_sub(*before) as f64 / length as f64
+                    })
+                    .collect::<Vec<_>>();
+                sent = now;
+
+                let current = running.inspect(|job| job.shard_weights.clone());
+                let Some(weights) = rebalanced_weights(num_shards, &current, &groups, &passes)
+                else {
+                    continue;
+                };
+                if !significant_change(&current, &weights) {
+                    continue;
+                }
+
+                let message = format!(
+                    "{}: rebalanced shard weights to {:?}",
+                    running.name(),
+                    weights
+                );
+                match running.update(|job| job.set_shard_weights(weights)) {
+                    Ok(()) => info!("{}", message),
+                    Err(e) => error!("{}: unable to rebalance: {:#}", running.name(), e),
+                }
+            }
+        });
+    }
+
+    /// Bytes sent by every connection so far
+    fn bytes_sent(&self) -> Vec<u64> {
+        self.connections
+            .iter()
+            .map(|connection| connection.stats.bytes_sent())
+            .collect()
+    }
+
+    /// Reloads the image of the job whenever it changes on disk.
+    pub fn watch(self: &Arc<Self>, cancellation: CancellationToken) {
+        let running = self.clone();
+
+        std::thread::spawn(move || {
+            let mut watched = None;
+            let mut watcher = None;
+
+            while cancellation.sleep(WATCH_INTERVAL) {
+                // another image might have been selected in the meantime, other generators
+                // don't draw one
+                let image = running.inspect(|job| match job.image_args.generator.as_str() {
+                    IMAGE_GENERATOR => job.image.clone(),
+                    _ => None,
+                });
+                if image != watched {
+                    watcher = image.as_deref().map(FileWatcher::new);
+                    watched = image;
+                }
+
+                if !watcher.as_mut().is_some_and(FileWatcher::changed) {
+                    continue;
+                }
+
+                match running.update(|job| job.reload()) {
+                    Ok(()) => info!("{}: reloaded image", running.name()),
+                    Err(e) => error!("{}: keeping previous image: {:#}", running.name(), e),
+                }
+            }
+        });
+    }
+}
+
+/// Scales the weight of every shard by the passes its connection got through, damped by taking
+/// the square root, so weights settle instead of overshooting.
+///
+/// `groups` holds the shards of every connection and `passes` how often it sent its payload.
+/// `None` if nothing was sent at all.
+fn rebalanced_weights(
+    num_shards: usize,
+    current: &[u32],
+    groups: &[&[usize]],
+    passes: &[f64],
+) -> Option<Vec<u32>> {
+    let mut weights = vec![0.0; num_shards];
+    for (shards, passes) in groups.iter().zip(passes) {
+        for &shard in shards.iter() {
+            let weight = match current.is_empty() {
+                true => 1.0,
+                false => current[shard] as f64,
+            };
+            weights[shard] = weight * passes.sqrt();
+        }
+    }
+
+    let heaviest = weights.iter().copied().fold(0.0, f64::max);
+    if heaviest == 0.0 {
+        return None;
+    }
+
+    let weights = weights
+        .into_iter()
+        .map(|weight| {
+            let weight = (weight / heaviest).max(MIN_REBALANCE_SHARE) * REBALANCE_SCALE;
+            weight.round() as u32
+        })
+        .collect();
+
+    Some(weights)
+}
+
+/// Whether the share of any shard differs by more than [`REBALANCE_THRESHOLD`]
+fn significant_change(current: &[u32], new: &[u32]) -> bool {
+    let shares = |weights: &[u32]| {
+        let total = weights.iter().map(|&weight| weight as f64).sum::<f64>();
+
+        (0..new.len())
+            .map(|shard| match total > 0.0 {
+                true => weights.get(shard).copied().unwrap_or(0) as f64 / total,
+                false => 1.0 / new.len() as f64,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    shares(current)
+        .iter()
+        .zip(shares(new))
+        .any(|(current, new)| (current - new).abs() > REBALANCE_THRESHOLD)
+}
+
+/// Connects to the target of a job and starts sending the payload on a new thread.
+fn open(
+    job: &Job,
+    target: &Target,
+    shards: &[usize],
+    payload: Payload,
+    options: SendOptions,
+    cancellation: &CancellationToken,
+) -> Option<(Connection, JoinHandle<()>)> {
+    let label = format!("{} shards {:?}", job.name, shards);
+
+    let stream = match StreamWrapper::new(target.clone())
+        .loops(options.loops)
+        .cancellation(cancellation.clone())
+        .connect()
+    {
+        Ok(stream) => {
+            info!("{} connected successfully", label);
+            stream
+        }
+        Err(e) => {
+            error!("{} failed to connect: {}", label, e);
+            return None;
+        }
+    };
+
+    let payload = SharedPayload::new(payload);
+    let stats = stream.stats();
+    let throttle = stream.throttle();
+    throttle.set_rate_limit(options.rate_limit);
+
+    let handle = {
+        let label = label.clone();
+        let payload = payload.clone();
+        std::thread::spawn(move || {
+            stream.send(payload);
+
+            info!("{} disconnected", label)
+        })
+    };
+
+    let connection = Connection {
+        label,
+        shards: shards.to_vec(),
+        payload,
+        stats,
+        throttle,
+    };
+
+    Some((connection, handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cancel::CancellationToken;
+    use crate::command_generator::animation::Frame;
+    use crate::dump::Destination;
+    use crate::job::{ImageArgs, Job, IMAGE_GENERATOR};
+    use crate::runner::{rebalanced_weights, significant_change, RunningJob, SendOptions};
+    use crate::stream::mock::MockServer;
+    use crate::stream::transport::Target;
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use std::time::{Duration, Instant};
+
+    /// Waits up to a few seconds for `condition` to hold
+    fn eventually(condition: impl Fn() -> bool) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        false
+    }
+
+    #[test]
+    fn test_animation_follows_updates() {
+        let server = MockServer::new().start();
+        let target = Target::Tcp(server.addr);
+        let cancellation = CancellationToken::default();
+        let args = ImageArgs {
+            generator: "fill".into(),
+            input: Some("ff0000".into()),
+            width: Some(1),
+            height: Some(1),
+            ..ImageArgs::default()
+        };
+        let job = Job::new(
+            "test".into(),
+            Destination::Server(target.clone()),
+            None,
+            args,
+            vec![0],
+            1,
+            Default::default(),
+        )
+        .unwrap();
+
+        let (running, handles) =
+            RunningJob::start(job, target, 1, SendOptions::default(), &cancellation);
+        let animating = || *running.animating.lock().unwrap();
+        let sending = |color: &str| {
+            let payload = running.connections()[0].payload.load();
+            std::str::from_utf8(&payload.segments()[0])
+                .unwrap()
+                .contains(color)
+        };
+        assert!(!animating());
+
+        let frames = [Rgba([0, 0, 255, 255]), Rgba([0, 255, 0, 255])]
+            .into_iter()
+            .map(|color| Frame {
+                image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, color)),
+                delay: Duration::from_millis(20),
+            })
+            .collect::<Vec<_>>();
+        running
+            .update(|job| {
+                job.image_args.generator = IMAGE_GENERATOR.into();
+                job.original = Some(frames[0].image.clone());
+                job.frames = frames.into();
+                job.rebuild()
+            })
+            .unwrap();
+        assert!(animating());
+        assert!(eventually(|| sending("00ff00ff")));
+        assert!(eventually(|| sending("0000ffff")));
+
+        let still = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        running
+            .update(|job| job.set_image(DynamicImage::ImageRgba8(still)))
+            .unwrap();
+        assert!(eventually(|| !animating()));
+        assert!(sending("ffffffff"));
+
+        cancellation.cancel();
+        handles
+            .into_iter()
+            .for_each(|handle| handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_nudges_are_combined() {
+        let server = MockServer::new().start();
+        let target = Target::Tcp(server.addr);
+        let cancellation = CancellationToken::default();
+        let args = ImageArgs {
+            generator: "fill".into(),
+            input: Some("ff0000".into()),
+            width: Some(1),
+            height: Some(1),
+            ..ImageArgs::default()
+        };
+        let job = Job::new(
+            "test".into(),
+            Destination::Server(target.clone()),
+            None,
+            args,
+            vec![0],
+            1,
+            Default::default(),
+        )
+        .unwrap();
+
+        let (running, handles) =
+            RunningJob::start(job, target, 1, SendOptions::default(), &cancellation);
+        for _ in 0..3 {
+            running.nudge(1, 0);
+        }
+        running.nudge(0, 2);
+        running.nudge(-1, 0);
+
+        assert!(eventually(|| running.moving.lock().unwrap().is_none()));
+        assert_eq!(
+            running.inspect(|job| (job.image_args.offset_x, job.image_args.offset_y)),
+            (2, 2)
+        );
+        let payload = running.connections()[0].payload.load();
+        assert!(std::str::from_utf8(&payload.segments()[0])
+            .unwrap()
+            .contains("PX 2 2 "));
+
+        cancellation.cancel();
+        handles
+            .into_iter()
+            .for_each(|handle| handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_rebalanced_weights() {
+        let groups: [&[usize]; 3] = [&[0, 3], &[1], &[2]];
+
+        assert_eq!(
+            rebalanced_weights(4, &[], &groups, &[16.0, 4.0, 0.0]),
+            Some(vec![1000, 500, 50, 1000])
+        );
+        assert_eq!(
+            rebalanced_weights(4, &[4, 1, 1, 2], &groups, &[1.0, 4.0, 16.0]),
+            Some(vec![1000, 500, 1000, 500])
+        );
+        assert_eq!(rebalanced_weights(4, &[], &groups, &[0.0, 0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_rebalancing_is_stable_when_passes_are_equal() {
+        let groups: [&[usize]; 3] = [&[0], &[1], &[2]];
+        let weights = rebalanced_weights(3, &[4, 1, 1], &groups, &[5.0, 5.0, 5.0]).unwrap();
+
+        assert!(!significant_change(&[4, 1, 1], &weights));
+    }
+
+    #[test]
+    fn test_significant_change() {
+        assert!(!significant_change(&[], &[10, 10]));
+        assert!(!significant_change(&[100, 101], &[101, 100]));
+        assert!(significant_change(&[], &[4, 1]));
+    }
+}
@@ -0,0 +1,221 @@
+use schwitzerflut_protocol::canvas::Canvas;
+use schwitzerflut_protocol::command::Command;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Size of the reads of the mock, small enough for slow reads to apply backpressure
+const READ_SIZE: usize = 1024;
+
+/// How long [`RunningMockServer::wait_idle`] waits for connections to finish
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// In-process Pixelflut server for tests that misbehaves on request
+#[derive(Clone)]
+pub struct MockServer {
+    width: u32,
+    height: u32,
+    latency: Duration,
+    read_delay: Duration,
+    disconnects: usize,
+    disconnect_within: usize,
+    malformed_replies: bool,
+    seed: u64,
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            latency: Duration::ZERO,
+            read_delay: Duration::ZERO,
+            disconnects: 0,
+            disconnect_within: 0,
+            malformed_replies: false,
+            seed: 0x5eed,
+        }
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Delay before every reply
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Delay after every read, so clients fill up the socket buffers
+    pub fn slow_reads(mut self, delay: Duration) -> Self {
+        self.read_delay = delay;
+        self
+    }
+
+    /// Drops the first `count` connections after a random number of bytes, at most `within`
+    pub fn disconnects(mut self, count: usize, within: usize) -> Self {
+        self.disconnects = count;
+        self.disconnect_within = within;
+        self
+    }
+
+    /// Replies with garbage to every query
+    pub fn malformed_replies(mut self, malformed: bool) -> Self {
+        self.malformed_replies = malformed;
+        self
+    }
+
+    /// Seed of the random disconnects
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Listens on a random local port, accepting connections on a background thread
+    pub fn start(self) -> RunningMockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let running = RunningMockServer {
+            addr: listener.local_addr().unwrap(),
+            canvas: Arc::new(Canvas::new(self.width, self.height)),
+            received: Arc::new(Mutex::new(Vec::new())),
+            connections: Arc::new(AtomicUsize::new(0)),
+            active: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let mut rng = Rng(self.seed | 1);
+        let handle = running.clone();
+
+        std::thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let Ok(stream) = stream else { continue };
+
+                let limit = (index < self.disconnects)
+                    .then(|| 1 + rng.next() as usize % self.disconnect_within.max(1));
+                let config = self.clone();
+                let handle = handle.clone();
+
+                handle.connections.fetch_add(1, Ordering::SeqCst);
+                handle.active.fetch_add(1, Ordering::SeqCst);
+
+                std::thread::spawn(move || {
+                    let _ = config.serve(&handle, stream, limit);
+                    handle.active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        running
+    }
+
+    fn serve(
+        &self,
+        handle: &RunningMockServer,
+        stream: TcpStream,
+        limit: Option<usize>,
+    ) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::with_capacity(READ_SIZE, stream);
+        let mut line = Vec::new();
+        let mut received = 0;
+
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(());
+            }
+
+            let mut length = buffer.len();
+            if let Some(limit) = limit {
+                length = length.min(limit - received);
+            }
+
+            let chunk = buffer[..length].to_vec();
+            reader.consume(length);
+            received += length;
+            handle.received.lock().unwrap().extend_from_slice(&chunk);
+
+            for &byte in &chunk {
+                if byte != b'\n' {
+                    line.push(byte);
+                    continue;
+                }
+
+                if let Some(reply) = self.execute(handle, &line) {
+                    std::thread::sleep(self.latency);
+                    writeln!(writer, "{reply}")?;
+                }
+                line.clear();
+            }
+
+            // dropping the connection with unread data resets it
+            if limit == Some(received) {
+                return Ok(());
+            }
+
+            std::thread::sleep(self.read_delay);
+        }
+    }
+
+    fn execute(&self, handle: &RunningMockServer, line: &[u8]) -> Option<String> {
+        let command = std::str::from_utf8(line)
+            .ok()?
+            .trim_end_matches('\r')
+            .parse::<Command>()
+            .ok()?;
+        let response = handle.canvas.execute(&command)?;
+
+        Some(match self.malformed_replies {
+            true => format!("{} \u{1f4a9}", &response.to_string()[..2]),
+            false => response.to_string(),
+        })
+    }
+}
+
+/// Handle to a started [`MockServer`]
+#[derive(Clone)]
+pub struct RunningMockServer {
+    pub addr: SocketAddr,
+    pub canvas: Arc<Canvas>,
+    received: Arc<Mutex<Vec<u8>>>,
+    connections: Arc<AtomicUsize>,
+    active: Arc<AtomicUsize>,
+}
+
+impl RunningMockServer {
+    /// Everything received on all connections so far
+    pub fn received(&self) -> Vec<u8> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Number of connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until `connections` have been accepted and all of them have been closed.
+    pub fn wait_idle(&self, connections: usize) {
+        let started = Instant::now();
+
+        while self.connections() < connections || self.active.load(Ordering::SeqCst) > 0 {
+            assert!(started.elapsed() < IDLE_TIMEOUT, "connections still open");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Xorshift, good enough to pick where to disconnect
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
@@ -0,0 +1,408 @@
+use crate::cancel::CancellationToken;
+use crate::output::error;
+use crate::stream::payload::{Payload, SharedPayload};
+use crate::stream::stats::{ConnectionState, Stats};
+use crate::stream::throttle::Throttle;
+use crate::stream::transport::{Connection, Transport};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+pub mod mock;
+pub mod payload;
+#[cfg(test)]
+pub mod query;
+pub mod stats;
+pub mod throttle;
+pub mod transport;
+
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How long to wait before checking again whether an empty payload has been replaced
+const EMPTY_PAYLOAD_DELAY: Duration = Duration::from_millis(500);
+
+/// How often a paused connection checks whether it has been resumed
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a rate limited connection checks whether its limit changed while waiting
+const PACE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Payloads are written in chunks of this size so cancellation is noticed within a single pass
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How long a write blocks before checking for cancellation, so a server that stopped reading
+/// can't keep a connection from stopping
+const WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+pub struct Disconnected;
+pub struct Connected;
+
+pub struct StreamWrapper<T: Transport, S> {
+    transport: T,
+    stream: Option<T::Stream>,
+    stats: Arc<Stats>,
+    throttle: Arc<Throttle>,
+    reconnect_attempts: u32,
+    loops: Option<u64>,
+    cancellation: CancellationToken,
+    _state: PhantomData<S>,
+}
+
+impl<T: Transport> StreamWrapper<T, Disconnected> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            stream: None,
+            stats: Arc::new(Stats::default()),
+            throttle: Arc::new(Throttle::default()),
+            reconnect_attempts: 10,
+            loops: None,
+            cancellation: CancellationToken::default(),
+            _state: PhantomData,
+        }
+    }
+
+    /// How often to retry connecting after the connection was lost before giving up.
+    pub fn reconnect_attempts(mut self, attempts: u32) -> Self {
+        self.reconnect_attempts = attempts;
+        self
+    }
+
+    /// How often to send the full payload before returning. Loops forever if `None`.
+    pub fn loops(mut self, loops: Option<u64>) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    /// Token that stops sending once it is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    pub fn connect(self) -> io::Result<StreamWrapper<T, Connected>> {
+        let stream = open(&self.transport).inspect_err(|_| {
+            self.stats.set_state(ConnectionState::Closed);
+        })?;
+        self.stats.set_state(ConnectionState::Connected);
+
+        Ok(StreamWrapper {
+            transport: self.transport,
+            stream: Some(stream),
+            stats: self.stats,
+            throttle: self.throttle,
+            reconnect_attempts: self.reconnect_attempts,
+            loops: self.loops,
+            cancellation: self.cancellation,
+            _state: PhantomData,
+        })
+    }
+}
+
+impl<T: Transport, S> StreamWrapper<T, S> {
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    /// Handle to pause, resume and rate limit the connection while it is sending
+    pub fn throttle(&self) -> Arc<Throttle> {
+        self.throttle.clone()
+    }
+}
+
+impl<T: Transport> StreamWrapper<T, Connected> {
+    /// Sends the payload in a loop, reconnecting whenever the connection is lost.
+    ///
+    /// The payload is reloaded before every pass, so it can be replaced while sending. Returns once
+    /// the configured number of loops has been sent, the cancellation token has been cancelled or
+    /// reconnecting failed `reconnect_attempts` times in a row.
+    pub fn send(mut self, payload: SharedPayload) {
+        let mut connection = self.stream.take().unwrap();
+
+        while !self.cancellation.is_cancelled()
+            && self
+                .loops
+                .is_none_or(|loops| self.stats.payloads_sent() < loops)
+        {
+            let payload = payload.load();
+
+            if payload.is_empty() {
+                // with a loop limit there is nothing to wait for, otherwise it might get replaced
+                if self.loops.is_some() {
+                    break;
+                }
+
+                self.cancellation.sleep(EMPTY_PAYLOAD_DELAY);
+                continue;
+            }
+
+            match self.write_payload(&mut connection, &payload) {
+                Ok(true) => self.stats.record_payload(),
+                Ok(false) => break,
+                Err(e) => {
+                    error!("error sending: {}", e);
+
+                    match self.reconnect() {
+                        Some(stream) => connection = stream,
+                        None => {
+                            self.stats.set_state(ConnectionState::Closed);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+
+        let _ = connection.flush();
+        let _ = connection.shutdown();
+        self.stats.set_state(ConnectionState::Closed);
+    }
+
+    /// Writes one pass of the payload. Returns `false` if it was interrupted by cancellation.
+    fn write_payload(&self, connection: &mut T::Stream, payload: &Payload) -> io::Result<bool> {
+        let chunks = payload
+            .segments()
+            .iter()
+            .flat_map(|segment| segment.chunks(CHUNK_SIZE));
+
+        for chunk in chunks {
+            while self.throttle.is_paused() && self.cancellation.sleep(PAUSE_POLL_INTERVAL) {}
+
+            if self.cancellation.is_cancelled() {
+                return Ok(false);
+            }
+
+            if !self.write_all(connection, chunk)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Writes all of `data` within the rate limit. Returns `false` if cancelled while the server is
+    /// not reading.
+    fn write_all(&self, connection: &mut T::Stream, mut data: &[u8]) -> io::Result<bool> {
+        while !data.is_empty() {
+            let started = Instant::now();
+            let size = self.throttle.write_size(data.len());
+
+            match connection.write(&data[..size]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.stats.record_sent(written);
+                    data = &data[written..];
+                    self.pace(written, started);
+                }
+                Err(e) if is_retryable(&e) => {
+                    if self.cancellation.is_cancelled() {
+                        return Ok(false);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Sleeps until sending `bytes` since `started` is within the rate limit. The limit is checked
+    /// again while sleeping, so changes apply right away.
+    fn pace(&self, bytes: usize, started: Instant) {
+        while let Some(remaining) = self.throttle.budget(bytes).checked_sub(started.elapsed()) {
+            if remaining.is_zero() || !self.cancellation.sleep(remaining.min(PACE_INTERVAL)) {
+                break;
+            }
+        }
+    }
+
+    fn reconnect(&self) -> Option<T::Stream> {
+        let mut delay = INITIAL_RECONNECT_DELAY;
+        self.stats.set_state(ConnectionState::Reconnecting);
+
+        for _ in 0..self.reconnect_attempts {
+            if !self.cancellation.sleep(delay) {
+                return None;
+            }
+
+            match open(&self.transport) {
+                Ok(stream) => {
+                    self.stats.record_reconnect();
+                    self.stats.set_state(ConnectionState::Connected);
+                    return Some(stream);
+                }
+                Err(e) => {
+                    error!("error reconnecting: {}", e);
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Connects with [`WRITE_TIMEOUT`] set
+fn open<T: Transport>(transport: &T) -> io::Result<T::Stream> {
+    let stream = transport.connect()?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    Ok(stream)
+}
+
+/// Whether a write failed without losing the connection, e.g. because it timed out
+fn is_retryable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cancel::CancellationToken;
+    use crate::stream::mock::MockServer;
+    use crate::stream::payload::{LineEnding, PayloadBuilder, SharedPayload};
+    use crate::stream::StreamWrapper;
+    use schwitzerflut_protocol::color::{Color, RgbColor};
+    use schwitzerflut_protocol::command::{Command, SetPixelCommand};
+    use schwitzerflut_protocol::coordinates::Coordinates;
+    use std::time::{Duration, Instant};
+
+    fn send(addr: std::net::SocketAddr, payload: &[u8], loops: u64) -> (u64, u64) {
+        let stream = StreamWrapper::new(addr)
+            .loops(Some(loops))
+            .connect()
+            .unwrap();
+        let stats = stream.stats();
+
+        stream.send(SharedPayload::new(payload.to_vec()));
+
+        (stats.payloads_sent(), stats.reconnects())
+    }
+
+    #[test]
+    fn test_send() {
+        let mock = MockServer::new().start();
+        let payload = b"PX 0 0 ff0000\nPX 1 0 00ff00\n";
+
+        assert_eq!(send(mock.addr, payload, 2), (2, 0));
+        mock.wait_idle(1);
+
+        assert_eq!(mock.received(), payload.repeat(2));
+        assert_eq!(
+            mock.canvas.get(Coordinates::new(1, 0)),
+            Some(RgbColor::new(0, 0xff, 0))
+        );
+    }
+
+    #[test]
+    fn test_looped_payload_decodes_into_commands() {
+        let mock = MockServer::new().start();
+        let commands = (0..3)
+            .map(|x| {
+                Command::SetPixel(SetPixelCommand::new(
+                    Coordinates::new(x, 0),
+                    Color::Rgb(RgbColor::new(0xff, 0, 0)),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let mut builder = PayloadBuilder::new().line_ending(LineEnding::CrLf);
+        builder.extend(commands.clone());
+
+        assert_eq!(send(mock.addr, &builder.build(), 4), (4, 0));
+        mock.wait_idle(1);
+
+        let received = String::from_utf8(mock.received()).unwrap();
+        let decoded = received
+            .strip_suffix("\r\n")
+            .unwrap()
+            .split("\r\n")
+            .map(|line| line.parse::<Command>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            decoded,
+            commands
+                .iter()
+                .cycle()
+                .take(12)
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_send_with_slow_reads() {
+        let mock = MockServer::new()
+            .slow_reads(Duration::from_micros(200))
+            .start();
+        let payload = b"PX 1 1 ffffff\n".repeat(16 * 1024);
+
+        assert_eq!(send(mock.addr, &payload, 1), (1, 0));
+        mock.wait_idle(1);
+
+        assert_eq!(mock.received().len(), payload.len());
+    }
+
+    #[test]
+    fn test_reconnect_after_disconnects() {
+        let mock = MockServer::new().disconnects(2, 64 * 1024).start();
+        // large enough to outgrow the socket buffers, so the disconnect is noticed mid-payload
+        let payload = b"PX 1 1 ffffff\n".repeat(512 * 1024);
+
+        assert_eq!(send(mock.addr, &payload, 1), (1, 2));
+        mock.wait_idle(3);
+
+        assert_eq!(mock.connections(), 3);
+        assert!(mock.received().ends_with(&payload));
+    }
+
+    #[test]
+    fn test_cancel_while_server_stalls() {
+        // the server stops reading, so writes block once the socket buffers are full
+        let mock = MockServer::new()
+            .slow_reads(Duration::from_secs(60))
+            .start();
+        let cancellation = CancellationToken::default();
+        let stream = StreamWrapper::new(mock.addr)
+            .cancellation(cancellation.clone())
+            .connect()
+            .unwrap();
+        let stats = stream.stats();
+        let started = Instant::now();
+
+        cancellation.cancel_after(Duration::from_millis(300));
+        stream.send(SharedPayload::new(
+            b"PX 1 1 ffffff\n".repeat(4 * 1024 * 1024),
+        ));
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(stats.payloads_sent(), 0);
+    }
+
+    #[test]
+    fn test_rate_limit_changes_apply_while_waiting() {
+        let mock = MockServer::new().start();
+        let stream = StreamWrapper::new(mock.addr)
+            .loops(Some(1))
+            .connect()
+            .unwrap();
+        let throttle = stream.throttle();
+        let started = Instant::now();
+
+        // would take almost two minutes at this rate
+        throttle.set_rate_limit(Some(100));
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            throttle.set_rate_limit(None);
+        });
+        stream.send(SharedPayload::new(b"PX 1 1 ffffff\n".repeat(800)));
+
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(300));
+        assert!(elapsed < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,201 @@
+use clap::ValueEnum;
+use schwitzerflut_protocol::command::Command;
+use serde::Deserialize;
+use std::io::Write;
+use std::sync::{Arc, RwLock};
+
+/// Terminator written after every command
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    /// For servers that expect `\r\n`
+    #[value(name = "crlf")]
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Self::Lf => b"\n",
+            Self::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// Encodes commands into a buffer that can be sent in a loop.
+///
+/// Every command is terminated, so the last command of one pass never runs into the first one of
+/// the next.
+#[derive(Debug, Default)]
+pub struct PayloadBuilder {
+    buffer: Vec<u8>,
+    line_ending: LineEnding,
+}
+
+impl PayloadBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    pub fn push(&mut self, command: &Command) {
+        // writing into a Vec can't fail
+        let _ = write!(self.buffer, "{command}");
+        self.buffer.extend_from_slice(self.line_ending.as_bytes());
+    }
+
+    pub fn extend(&mut self, commands: impl IntoIterator<Item = Command>) {
+        for command in commands {
+            self.push(&command);
+        }
+    }
+
+    pub fn build(self) -> Arc<[u8]> {
+        self.buffer.into()
+    }
+}
+
+/// Commands sent by a connection in one pass.
+///
+/// Made up of immutable buffers, usually one per shard, so connections drawing the same shard
+/// share its buffer instead of copying it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Payload(Arc<[Arc<[u8]>]>);
+
+impl Payload {
+    pub fn new(segments: Vec<Arc<[u8]>>) -> Self {
+        Self(segments.into())
+    }
+
+    pub fn segments(&self) -> &[Arc<[u8]>] {
+        &self.0
+    }
+
+    /// Total number of bytes in all segments
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|segment| segment.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|segment| segment.is_empty())
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(vec![bytes.into()])
+    }
+}
+
+/// Payload shared between a sending thread and whoever wants to replace it while it is running.
+///
+/// Replacements are picked up at the start of the next pass, so a pass never mixes two payloads.
+#[derive(Clone, Debug)]
+pub struct SharedPayload(Arc<RwLock<Payload>>);
+
+impl SharedPayload {
+    pub fn new(payload: impl Into<Payload>) -> Self {
+        Self(Arc::new(RwLock::new(payload.into())))
+    }
+
+    pub fn load(&self) -> Payload {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn store(&self, payload: impl Into<Payload>) {
+        *self.0.write().unwrap() = payload.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::payload::{LineEnding, Payload, PayloadBuilder, SharedPayload};
+    use schwitzerflut_protocol::color::{Color, RgbColor, RgbaColor};
+    use schwitzerflut_protocol::command::{Command, SetPixelCommand};
+    use schwitzerflut_protocol::coordinates::Coordinates;
+    use std::sync::Arc;
+
+    fn commands() -> Vec<Command> {
+        vec![
+            Command::SetPixel(SetPixelCommand::new(
+                Coordinates::new(0, 0),
+                Color::Rgb(RgbColor::new(0xff, 0, 0)),
+            )),
+            Command::SetPixel(SetPixelCommand::new(
+                Coordinates::new(1337, 42),
+                Color::Rgba(RgbaColor::new(RgbColor::new(0xc0, 0xff, 0xee), 0x80)),
+            )),
+        ]
+    }
+
+    /// Splits a stream into lines and parses every line as a command
+    fn decode(stream: &[u8], line_ending: LineEnding) -> Vec<Command> {
+        let stream = std::str::from_utf8(stream).unwrap();
+        let terminator = std::str::from_utf8(line_ending.as_bytes()).unwrap();
+
+        let lines = stream
+            .strip_suffix(terminator)
+            .expect("unterminated command");
+        lines
+            .split(terminator)
+            .map(|line| line.parse().unwrap())
+            .collect()
+    }
+
+    fn repeated(commands: Vec<Command>, times: usize) -> Vec<Command> {
+        let count = commands.len() * times;
+        commands.into_iter().cycle().take(count).collect()
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let mut builder = PayloadBuilder::new();
+        builder.extend(commands());
+
+        assert_eq!(
+            std::str::from_utf8(&builder.build()).unwrap(),
+            "PX 0 0 ff0000\nPX 1337 42 c0ffee80\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_looped_payload() {
+        for line_ending in [LineEnding::Lf, LineEnding::CrLf] {
+            let mut builder = PayloadBuilder::new().line_ending(line_ending);
+            builder.extend(commands());
+
+            let looped = builder.build().repeat(3);
+
+            assert_eq!(decode(&looped, line_ending), repeated(commands(), 3));
+        }
+    }
+
+    #[test]
+    fn test_store_replaces_payload_for_all_clones() {
+        let payload = SharedPayload::new(b"PX 0 0 ffffff\n".to_vec());
+        let clone = payload.clone();
+        let loaded = payload.load();
+
+        clone.store(b"PX 1 1 000000\n".to_vec());
+
+        assert_eq!(loaded, Payload::from(b"PX 0 0 ffffff\n".to_vec()));
+        assert_eq!(payload.load(), Payload::from(b"PX 1 1 000000\n".to_vec()));
+    }
+
+    #[test]
+    fn test_segments_are_shared() {
+        let shard: Arc<[u8]> = Arc::from(&b"PX 0 0 ffffff\n"[..]);
+        let first = Payload::new(vec![shard.clone()]);
+        let second = Payload::new(vec![shard.clone(), Arc::from(&b"PX 1 1 000000\n"[..])]);
+
+        assert!(Arc::ptr_eq(&first.segments()[0], &second.segments()[0]));
+        assert_eq!(second.len(), 28);
+        assert!(Payload::new(vec![Arc::from(&b""[..])]).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::stream::transport::Connection;
+use schwitzerflut_protocol::command::GetCanvasSizeCommand;
+use schwitzerflut_protocol::response::{CanvasSizeResponse, ParseResponseError, Response};
+use std::io::{self, Read};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Longest reply line accepted from a server
+const MAX_REPLY_LENGTH: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("connection closed before a reply was received")]
+    Closed,
+
+    #[error("malformed reply {reply:?}: {source}")]
+    Malformed {
+        reply: String,
+        source: ParseResponseError,
+    },
+
+    #[error("unexpected reply {0:?}")]
+    Unexpected(String),
+}
+
+/// Asks the server for the size of its canvas, waiting at most `timeout` for each read.
+///
+/// Only used by tests, to check the replies of servers like the mock one.
+pub fn canvas_size(
+    connection: &mut impl Connection,
+    timeout: Duration,
+) -> Result<CanvasSizeResponse, QueryError> {
+    connection.set_read_timeout(Some(timeout))?;
+    writeln!(connection, "{}", GetCanvasSizeCommand)?;
+    connection.flush()?;
+
+    let reply = read_line(connection)?;
+    connection.set_read_timeout(None)?;
+
+    match reply.parse::<Response>() {
+        Ok(Response::CanvasSize(size)) => Ok(size),
+        Ok(_) => Err(QueryError::Unexpected(reply)),
+        Err(source) => Err(QueryError::Malformed { reply, source }),
+    }
+}
+
+/// Reads a single line byte by byte, so nothing after it is consumed
+#[allow(clippy::unbuffered_bytes)]
+fn read_line(connection: &mut impl Read) -> Result<String, QueryError> {
+    let mut line = Vec::new();
+
+    for byte in connection.bytes() {
+        match byte? {
+            b'\n' => {
+                let line = String::from_utf8_lossy(&line);
+                return Ok(line.trim_end_matches('\r').to_string());
+            }
+            _ if line.len() == MAX_REPLY_LENGTH => {
+                return Err(QueryError::Unexpected(
+                    String::from_utf8_lossy(&line).into_owned(),
+                ))
+            }
+            byte => line.push(byte),
+        }
+    }
+
+    Err(QueryError::Closed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::mock::MockServer;
+    use crate::stream::query::{canvas_size, QueryError};
+    use crate::stream::transport::Transport;
+    use schwitzerflut_protocol::response::CanvasSizeResponse;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_canvas_size() {
+        let mock = MockServer::new().size(1920, 1080).start();
+        let mut connection = mock.addr.connect().unwrap();
+
+        assert_eq!(
+            canvas_size(&mut connection, TIMEOUT).unwrap(),
+            CanvasSizeResponse::new(1920, 1080)
+        );
+    }
+
+    #[test]
+    fn test_canvas_size_malformed_reply() {
+        let mock = MockServer::new().malformed_replies(true).start();
+        let mut connection = mock.addr.connect().unwrap();
+
+        assert!(matches!(
+            canvas_size(&mut connection, TIMEOUT),
+            Err(QueryError::Malformed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_canvas_size_timeout() {
+        let mock = MockServer::new()
+            .latency(Duration::from_millis(500))
+            .start();
+        let mut connection = mock.addr.connect().unwrap();
+
+        assert!(matches!(
+            canvas_size(&mut connection, Duration::from_millis(50)),
+            Err(QueryError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_canvas_size_disconnect() {
+        let mock = MockServer::new().disconnects(1, 2).start();
+        let mut connection = mock.addr.connect().unwrap();
+
+        assert!(matches!(
+            canvas_size(&mut connection, TIMEOUT),
+            Err(QueryError::Closed | QueryError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Share of a second of the rate limit a single write may use up, so rate limited connections
+/// send evenly instead of in bursts
+const WRITE_SLICE: Duration = Duration::from_millis(50);
+
+/// Live adjustable limits of a single connection, shared between the sending thread and controllers
+#[derive(Default, Debug)]
+pub struct Throttle {
+    paused: AtomicBool,
+    /// Maximum bytes per second, zero meaning unlimited
+    rate_limit: AtomicU64,
+}
+
+impl Throttle {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_rate_limit(&self, bytes_per_second: Option<u64>) {
+        self.rate_limit
+            .store(bytes_per_second.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    pub fn rate_limit(&self) -> Option<u64> {
+        match self.rate_limit.load(Ordering::Relaxed) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Largest write of at most `max` bytes that stays within [`WRITE_SLICE`] of the rate limit
+    pub fn write_size(&self, max: usize) -> usize {
+        match self.rate_limit() {
+            Some(limit) => ((limit as f64 * WRITE_SLICE.as_secs_f64()) as usize)
+                .max(1)
+                .min(max),
+            None => max,
+        }
+    }
+
+    /// Minimum time sending the given number of bytes has to take to stay within the rate limit
+    pub fn budget(&self, bytes: usize) -> Duration {
+        self.rate_limit()
+            .map(|limit| Duration::from_secs_f64(bytes as f64 / limit as f64))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::throttle::Throttle;
+    use std::time::Duration;
+
+    #[test]
+    fn test_unlimited_budget() {
+        assert_eq!(Throttle::default().budget(1024), Duration::ZERO)
+    }
+
+    #[test]
+    fn test_rate_limited_budget() {
+        let throttle = Throttle::default();
+        throttle.set_rate_limit(Some(2048));
+
+        assert_eq!(throttle.budget(1024), Duration::from_millis(500))
+    }
+
+    #[test]
+    fn test_write_size() {
+        let throttle = Throttle::default();
+        assert_eq!(throttle.write_size(4096), 4096);
+
+        throttle.set_rate_limit(Some(2048));
+        assert_eq!(throttle.write_size(4096), 102);
+        assert_eq!(throttle.write_size(10), 10);
+
+        throttle.set_rate_limit(Some(1));
+        assert_eq!(throttle.write_size(4096), 1);
+    }
+}
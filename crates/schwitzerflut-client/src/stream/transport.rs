@@ -0,0 +1,250 @@
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::net::{AddrParseError, Shutdown, SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Something a [`StreamWrapper`](super::StreamWrapper) can open a byte stream to
+pub trait Transport: Send + 'static {
+    type Stream: Connection;
+
+    fn connect(&self) -> io::Result<Self::Stream>;
+}
+
+/// Byte stream opened by a [`Transport`]
+pub trait Connection: Read + Write + Send {
+    /// Shuts down both halves of the connection.
+    fn shutdown(&self) -> io::Result<()>;
+
+    /// Limits how long reads block, forever if `None`. Only queries in tests wait for replies.
+    #[cfg(test)]
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Limits how long writes block, forever if `None`.
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+
+    #[cfg(test)]
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+
+    #[cfg(test)]
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl Transport for SocketAddr {
+    type Stream = TcpStream;
+
+    fn connect(&self) -> io::Result<Self::Stream> {
+        TcpStream::connect(self)
+    }
+}
+
+/// Path to a unix domain socket
+#[cfg(unix)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnixSocket(pub PathBuf);
+
+#[cfg(unix)]
+impl Transport for UnixSocket {
+    type Stream = UnixStream;
+
+    fn connect(&self) -> io::Result<Self::Stream> {
+        UnixStream::connect(&self.0)
+    }
+}
+
+/// Transport selected at runtime, e.g. from the command line.
+///
+/// Parses either a socket address (`127.0.0.1:1337`) or a unix socket path prefixed with `unix:`
+/// (`unix:/run/pixelflut.sock`).
+#[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Target {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixSocket),
+}
+
+impl Transport for Target {
+    type Stream = TargetStream;
+
+    fn connect(&self) -> io::Result<Self::Stream> {
+        match self {
+            Self::Tcp(addr) => addr.connect().map(TargetStream::Tcp),
+            #[cfg(unix)]
+            Self::Unix(socket) => socket.connect().map(TargetStream::Unix),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = ParseTargetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some("") => Err(Self::Err::EmptyPath),
+            #[cfg(unix)]
+            Some(path) => Ok(Self::Unix(UnixSocket(PathBuf::from(path)))),
+            #[cfg(not(unix))]
+            Some(_) => Err(Self::Err::Unsupported),
+            None => Ok(Self::Tcp(s.parse()?)),
+        }
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = ParseTargetError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            #[cfg(unix)]
+            Self::Unix(socket) => write!(f, "unix:{}", socket.0.display()),
+        }
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ParseTargetError {
+    #[error("Expected a socket address or 'unix:<path>'")]
+    InvalidAddress(#[from] AddrParseError),
+
+    #[error("Path must not be empty")]
+    EmptyPath,
+
+    #[error("Unix sockets are not supported on this platform")]
+    Unsupported,
+}
+
+/// Stream opened by a [`Target`]
+pub enum TargetStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Read for TargetStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for TargetStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Connection for TargetStream {
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => Connection::shutdown(stream),
+            #[cfg(unix)]
+            Self::Unix(stream) => Connection::shutdown(stream),
+        }
+    }
+
+    #[cfg(test)]
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => Connection::set_read_timeout(stream, timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => Connection::set_read_timeout(stream, timeout),
+        }
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => Connection::set_write_timeout(stream, timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => Connection::set_write_timeout(stream, timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::transport::{ParseTargetError, Target, UnixSocket};
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_tcp_target() {
+        assert_eq!(
+            "127.0.0.1:1337".parse(),
+            Ok(Target::Tcp(SocketAddr::from(([127, 0, 0, 1], 1337))))
+        )
+    }
+
+    #[test]
+    fn test_parse_unix_target() {
+        assert_eq!(
+            "unix:/run/pixelflut.sock".parse(),
+            Ok(Target::Unix(UnixSocket(PathBuf::from(
+                "/run/pixelflut.sock"
+            ))))
+        )
+    }
+
+    #[test]
+    fn test_parse_empty_unix_path() {
+        assert_eq!("unix:".parse::<Target>(), Err(ParseTargetError::EmptyPath))
+    }
+
+    #[test]
+    fn test_parse_invalid_target() {
+        assert!("localhost".parse::<Target>().is_err())
+    }
+}
@@ -0,0 +1,396 @@
+use crate::cancel::CancellationToken;
+use crate::output;
+use crate::runner::{Connection, RunningJob};
+use crate::stream::stats::ConnectionState;
+use image::RgbaImage;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Sparkline, Table, TableState, Widget};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Interval in which throughput is sampled and the screen is redrawn
+const TICK: Duration = Duration::from_millis(250);
+
+/// Number of throughput samples kept per connection
+const HISTORY: usize = 120;
+
+/// Rate limit a connection starts with when it is limited for the first time
+const INITIAL_RATE_LIMIT: u64 = 1024 * 1024;
+
+const HELP: &str =
+    "q quit  ↑↓ select  p pause  P pause all  wasd move (shift: 10px)  +/- rate  0 unlimited";
+
+/// Interactive dashboard showing all connections until the user quits or every connection closed
+pub fn run(jobs: &[Arc<RunningJob>], cancellation: &CancellationToken) -> std::io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let result = Dashboard::new(jobs).run(&mut terminal, cancellation);
+    ratatui::restore();
+
+    result
+}
+
+struct Dashboard<'a> {
+    jobs: &'a [Arc<RunningJob>],
+    /// Job and connection index of every table row
+    rows: Vec<(usize, usize)>,
+    history: Vec<VecDeque<u64>>,
+    last_bytes: Vec<u64>,
+    table: TableState,
+}
+
+impl<'a> Dashboard<'a> {
+    fn new(jobs: &'a [Arc<RunningJob>]) -> Self {
+        let rows = jobs
+            .iter()
+            .enumerate()
+            .flat_map(|(job, running)| (0..running.connections().len()).map(move |c| (job, c)))
+            .collect::<Vec<_>>();
+
+        let last_bytes = rows
+            .iter()
+            .map(|&(job, c)| jobs[job].connections()[c].stats.bytes_sent())
+            .collect();
+
+        Self {
+            history: vec![VecDeque::with_capacity(HISTORY); rows.len()],
+            last_bytes,
+            table: TableState::default().with_selected((!rows.is_empty()).then_some(0)),
+            rows,
+            jobs,
+        }
+    }
+
+    fn run(
+        mut self,
+        terminal: &mut DefaultTerminal,
+        cancellation: &CancellationToken,
+    ) -> std::io::Result<()> {
+        let mut last_sample = Instant::now();
+
+        while !cancellation.is_cancelled() && !self.all_closed() {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(TICK.saturating_sub(last_sample.elapsed()))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                        cancellation.cancel();
+                    }
+                }
+            }
+
+            if last_sample.elapsed() >= TICK {
+                self.sample(last_sample.elapsed());
+                last_sample = Instant::now();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn connection(&self, row: usize) -> (&Arc<RunningJob>, &Connection) {
+        let (job, connection) = self.rows[row];
+        let job = &self.jobs[job];
+
+        (job, &job.connections()[connection])
+    }
+
+    fn selected(&self) -> Option<(&Arc<RunningJob>, &Connection)> {
+        self.table.selected().map(|row| self.connection(row))
+    }
+
+    fn all_closed(&self) -> bool {
+        (0..self.rows.len())
+            .all(|row| self.connection(row).1.stats.state() == ConnectionState::Closed)
+    }
+
+    fn sample(&mut self, elapsed: Duration) {
+        for row in 0..self.rows.len() {
+            let bytes = self.connection(row).1.stats.bytes_sent();
+            let rate = (bytes - self.last_bytes[row]) as f64 / elapsed.as_secs_f64();
+
+            let history = &mut self.history[row];
+            if history.len() == HISTORY {
+                history.pop_front();
+            }
+            history.push_back(rate as u64);
+
+            self.last_bytes[row] = bytes;
+        }
+    }
+
+    /// Handles a key press, returns `false` if the user wants to quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let step = if key.modifiers.contains(KeyModifiers::SHIFT) {
+            10
+        } else {
+            1
+        };
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                if let Some((_, connection)) = self.selected() {
+                    toggle_pause(&[connection]);
+                }
+            }
+            KeyCode::Char('P') => {
+                let connections = (0..self.rows.len())
+                    .map(|row| self.connection(row).1)
+                    .collect::<Vec<_>>();
+                toggle_pause(&connections);
+            }
+            KeyCode::Char(c @ ('w' | 'a' | 's' | 'd' | 'W' | 'A' | 'S' | 'D')) => {
+                let (dx, dy) = match c.to_ascii_lowercase() {
+                    'w' => (0, -step),
+                    'a' => (-step, 0),
+                    's' => (0, step),
+                    _ => (step, 0),
+                };
+
+                if let Some((job, _)) = self.selected() {
+                    job.nudge(dx, dy);
+                }
+            }
+            KeyCode::Char('+') => {
+                if let Some((_, connection)) = self.selected() {
+                    let limit = connection.throttle.rate_limit();
+                    connection
+                        .throttle
+                        .set_rate_limit(limit.map(|limit| limit.saturating_mul(2)));
+                }
+            }
+            KeyCode::Char('-') => {
+                if let Some((_, connection)) = self.selected() {
+                    let limit = connection
+                        .throttle
+                        .rate_limit()
+                        .unwrap_or(INITIAL_RATE_LIMIT * 2);
+                    connection.throttle.set_rate_limit(Some((limit / 2).max(1)));
+                }
+            }
+            KeyCode::Char('0') => {
+                if let Some((_, connection)) = self.selected() {
+                    connection.throttle.set_rate_limit(None);
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, log, help] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(6),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [left, preview] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(main);
+        let [table, sparklines] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(left);
+
+        self.draw_table(frame, table);
+        self.draw_sparklines(frame, sparklines);
+        self.draw_preview(frame, preview);
+
+        let lines = output::recent(log.height.saturating_sub(2) as usize)
+            .into_iter()
+            .map(Line::from)
+            .collect::<Vec<_>>();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("log")),
+            log,
+        );
+        frame.render_widget(
+            Line::from(HELP).style(Style::new().fg(Color::DarkGray)),
+            help,
+        );
+    }
+
+    fn draw_table(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = (0..self.rows.len())
+            .map(|row| {
+                let (_, connection) = self.connection(row);
+                let state = if connection.throttle.is_paused() {
+                    "paused".to_string()
+                } else {
+                    format!("{:?}", connection.stats.state()).to_lowercase()
+                };
+
+                Row::new([
+                    Cell::from(connection.label.clone()),
+                    Cell::from(state),
+                    Cell::from(format_bytes(self.history[row].back().copied().unwrap_or(0)) + "/s"),
+                    Cell::from(format_bytes(connection.stats.bytes_sent())),
+                    Cell::from(connection.stats.reconnects().to_string()),
+                    Cell::from(
+                        connection
+                            .throttle
+                            .rate_limit()
+                            .map(|limit| format_bytes(limit) + "/s")
+                            .unwrap_or_else(|| "-".into()),
+                    ),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(12),
+                Constraint::Length(11),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(11),
+            ],
+        )
+        .header(
+            Row::new(["connection", "state", "rate", "sent", "reconnects", "limit"])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title("connections"));
+
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_sparklines(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("throughput");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let height = 3;
+        let constraints = self.rows.iter().map(|_| Constraint::Length(height));
+        let areas = Layout::vertical(constraints).split(inner);
+
+        for (row, area) in areas.iter().enumerate() {
+            let (_, connection) = self.connection(row);
+            let [label, sparkline] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(*area);
+
+            // most recent samples on the right
+            let samples = self.history[row]
+                .iter()
+                .skip(
+                    self.history[row]
+                        .len()
+                        .saturating_sub(sparkline.width as usize),
+                )
+                .copied()
+                .collect::<Vec<_>>();
+
+            frame.render_widget(Line::from(connection.label.as_str()), label);
+            frame.render_widget(
+                Sparkline::default()
+                    .data(&samples)
+                    .style(Style::new().fg(Color::Green)),
+                sparkline,
+            );
+        }
+    }
+
+    fn draw_preview(&self, frame: &mut Frame, area: Rect) {
+        let Some((job, _)) = self.selected() else {
+            return;
+        };
+
+        let block = Block::bordered().title(job.name());
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        job.inspect(|job| Preview(&job.preview).render(inner, frame.buffer_mut()));
+    }
+}
+
+fn toggle_pause(connections: &[&Connection]) {
+    let paused = connections.iter().all(|c| c.throttle.is_paused());
+
+    for connection in connections {
+        if paused {
+            connection.throttle.resume();
+        } else {
+            connection.throttle.pause();
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Downscaled image drawn with half blocks, two pixels per cell
+struct Preview<'a>(&'a RgbaImage);
+
+impl Widget for Preview<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (width, height) = self.0.dimensions();
+        if width == 0 || height == 0 || area.is_empty() {
+            return;
+        }
+
+        let scale = (area.width as f64 / width as f64)
+            .min(area.height as f64 * 2.0 / height as f64)
+            .min(1.0);
+        let columns = ((width as f64 * scale) as u16).max(1);
+        let rows = ((height as f64 * scale / 2.0).ceil() as u16).max(1);
+
+        let pixel = |x: u16, y: u16| {
+            let x = ((x as f64 / scale) as u32).min(width - 1);
+            let y = (y as f64 / scale) as u32;
+
+            match self.0.get_pixel_checked(x, y) {
+                Some(pixel) if pixel[3] != 0 => Color::Rgb(pixel[0], pixel[1], pixel[2]),
+                _ => Color::Reset,
+            }
+        };
+
+        for row in 0..rows.min(area.height) {
+            for column in 0..columns.min(area.width) {
+                if let Some(cell) = buf.cell_mut((area.x + column, area.y + row)) {
+                    cell.set_char('▀')
+                        .set_fg(pixel(column, row * 2))
+                        .set_bg(pixel(column, row * 2 + 1));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tui::format_bytes;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
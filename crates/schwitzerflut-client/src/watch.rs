@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Detects modifications of a file by polling its metadata.
+///
+/// Polling instead of subscribing to filesystem events keeps working when editors replace the file
+/// through a rename instead of writing to it.
+pub struct FileWatcher {
+    path: PathBuf,
+    last_seen: Option<(SystemTime, u64)>,
+}
+
+impl FileWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let last_seen = Self::fingerprint(&path);
+
+        Self { path, last_seen }
+    }
+
+    /// Whether the file changed since the last call, or since the watcher was created
+    pub fn changed(&mut self) -> bool {
+        let current = Self::fingerprint(&self.path);
+
+        if current.is_none() || current == self.last_seen {
+            return false;
+        }
+
+        self.last_seen = current;
+        true
+    }
+
+    fn fingerprint(path: &Path) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::watch::FileWatcher;
+    use std::fs;
+
+    #[test]
+    fn test_detects_changes() {
+        let path = std::env::temp_dir().join(format!("schwitzerflut-watch-{}", std::process::id()));
+        fs::write(&path, "a").unwrap();
+
+        let mut watcher = FileWatcher::new(&path);
+        assert!(!watcher.changed());
+
+        fs::write(&path, "ab").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::color::{blend, Color, RgbColor};
+use crate::command::{Command, SetPixelCommand};
+use crate::coordinates::Coordinates;
+use crate::response::{CanvasSizeResponse, PixelResponse, Response};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Opaque RGBA framebuffer implementing the semantics of the protocol.
+///
+/// Every pixel is stored as packed `0x00rrggbb` so writes don't need a lock. Concurrent blends of
+/// the same pixel may lose one of the updates, which is fine for Pixelflut.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<AtomicU32>,
+}
+
+impl Canvas {
+    /// Creates a black canvas
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixels = (0..width as usize * height as usize)
+            .map(|_| AtomicU32::new(0))
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index(&self, coordinates: Coordinates) -> Option<usize> {
+        (coordinates.x < self.width && coordinates.y < self.height)
+            .then(|| coordinates.y as usize * self.width as usize + coordinates.x as usize)
+    }
+
+    /// Runs a command, returning the reply a server sends for it if there is one.
+    ///
+    /// Reads outside of the canvas are not answered.
+    pub fn execute(&self, command: &Command) -> Option<Response> {
+        match command {
+            Command::SetPixel(cmd) => {
+                self.apply(cmd);
+                None
+            }
+            Command::GetPixel(cmd) => self.get(cmd.coordinates).map(|color| {
+                Response::Pixel(PixelResponse::new(cmd.coordinates, Color::Rgb(color)))
+            }),
+            Command::GetCanvasSize(_) => Some(Response::CanvasSize(CanvasSizeResponse::new(
+                self.width,
+                self.height,
+            ))),
+            Command::Help(_) => None,
+        }
+    }
+
+    /// Reads a pixel, `None` if it lies outside of the canvas
+    pub fn get(&self, coordinates: Coordinates) -> Option<RgbColor> {
+        let pixel = self.pixels[self.index(coordinates)?].load(Ordering::Relaxed);
+
+        Some(unpack(pixel))
+    }
+
+    /// Draws a pixel, compositing colors with an alpha channel over the current one.
+    ///
+    /// Pixels outside of the canvas are ignored.
+    pub fn apply(&self, command: &SetPixelCommand) {
+        let Some(index) = self.index(command.coordinates) else {
+            return;
+        };
+
+        let pixel = &self.pixels[index];
+        let color = match command.color {
+            Color::Rgb(rgb) => rgb,
+            Color::Rgba(rgba) => blend(unpack(pixel.load(Ordering::Relaxed)), rgba.rgb, rgba.alpha),
+        };
+
+        pixel.store(pack(color), Ordering::Relaxed);
+    }
+
+    /// Copies the current contents as rows of RGBA bytes, e.g. for an image encoder
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                let color = unpack(pixel.load(Ordering::Relaxed));
+                [color.r, color.g, color.b, 0xff]
+            })
+            .collect()
+    }
+}
+
+fn pack(color: RgbColor) -> u32 {
+    u32::from_be_bytes([0, color.r, color.g, color.b])
+}
+
+fn unpack(pixel: u32) -> RgbColor {
+    let [_, r, g, b] = pixel.to_be_bytes();
+    RgbColor::new(r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canvas::Canvas;
+    use crate::color::{Color, RgbColor, RgbaColor};
+    use crate::command::{Command, SetPixelCommand};
+    use crate::coordinates::Coordinates;
+    use crate::response::{CanvasSizeResponse, PixelResponse, Response};
+
+    #[test]
+    fn test_set_rgb() {
+        let canvas = Canvas::new(4, 4);
+        let color = RgbColor::new(0xc0, 0xff, 0xee);
+
+        canvas.apply(&SetPixelCommand::new(
+            Coordinates::new(3, 2),
+            Color::Rgb(color),
+        ));
+
+        assert_eq!(canvas.get(Coordinates::new(3, 2)), Some(color));
+        assert_eq!(
+            canvas.get(Coordinates::new(2, 3)),
+            Some(RgbColor::new(0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_blend_rgba() {
+        let canvas = Canvas::new(1, 1);
+        let coordinates = Coordinates::new(0, 0);
+
+        canvas.apply(&SetPixelCommand::new(
+            coordinates,
+            Color::Rgb(RgbColor::new(0, 0, 0xff)),
+        ));
+        canvas.apply(&SetPixelCommand::new(
+            coordinates,
+            Color::Rgba(RgbaColor::new(RgbColor::new(0xff, 0, 0), 0x80)),
+        ));
+
+        assert_eq!(canvas.get(coordinates), Some(RgbColor::new(0x80, 0, 0x7f)));
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let canvas = Canvas::new(2, 2);
+
+        canvas.apply(&SetPixelCommand::new(
+            Coordinates::new(2, 0),
+            Color::Rgb(RgbColor::new(0xff, 0, 0)),
+        ));
+
+        assert_eq!(canvas.get(Coordinates::new(2, 0)), None);
+        assert_eq!(canvas.get(Coordinates::new(0, 2)), None);
+    }
+
+    #[test]
+    fn test_execute_queries() {
+        let canvas = Canvas::new(8, 4);
+
+        assert_eq!(
+            canvas.execute(&"SIZE".parse::<Command>().unwrap()),
+            Some(Response::CanvasSize(CanvasSizeResponse::new(8, 4)))
+        );
+        assert_eq!(canvas.execute(&"PX 1 1 ffffff".parse().unwrap()), None);
+        assert_eq!(
+            canvas.execute(&"PX 1 1".parse().unwrap()),
+            Some(Response::Pixel(PixelResponse::new(
+                Coordinates::new(1, 1),
+                Color::Rgb(RgbColor::new(0xff, 0xff, 0xff))
+            )))
+        );
+        assert_eq!(canvas.execute(&"PX 8 1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_to_rgba_bytes() {
+        let canvas = Canvas::new(2, 1);
+
+        canvas.apply(&SetPixelCommand::new(
+            Coordinates::new(1, 0),
+            Color::Rgb(RgbColor::new(1, 2, 3)),
+        ));
+
+        assert_eq!(canvas.to_rgba_bytes(), vec![0, 0, 0, 0xff, 1, 2, 3, 0xff]);
+    }
+}
@@ -25,6 +25,11 @@ impl FromStr for RgbColor {
             return Err(Self::Err::UnexpectedInputLength { length: s.len() });
         }
 
+        // slicing below would panic inside multi-byte characters
+        if !s.is_ascii() {
+            return Err(Self::Err::NonAscii);
+        }
+
         let r = u8::from_str_radix(&s[0..2], 16)?;
         let g = u8::from_str_radix(&s[2..4], 16)?;
         let b = u8::from_str_radix(&s[4..6], 16)?;
@@ -59,6 +64,11 @@ impl FromStr for RgbaColor {
             return Err(Self::Err::UnexpectedInputLength { length: s.len() });
         }
 
+        // slicing below would panic inside multi-byte characters
+        if !s.is_ascii() {
+            return Err(Self::Err::NonAscii);
+        }
+
         let rgb = s[..6].parse::<RgbColor>()?;
         let alpha = u8::from_str_radix(&s[6..8], 16)?;
 
@@ -99,6 +109,20 @@ impl Display for Color {
     }
 }
 
+/// Source-over compositing of a translucent color onto an opaque one
+pub fn blend(background: RgbColor, foreground: RgbColor, alpha: u8) -> RgbColor {
+    let channel = |background: u8, foreground: u8| {
+        let alpha = alpha as u32;
+        ((foreground as u32 * alpha + background as u32 * (255 - alpha) + 127) / 255) as u8
+    };
+
+    RgbColor::new(
+        channel(background.r, foreground.r),
+        channel(background.g, foreground.g),
+        channel(background.b, foreground.b),
+    )
+}
+
 #[derive(Error, Debug, Eq, PartialEq)]
 pub enum ParseColorError {
     #[error("Expected 6 or 8 chars of input, got {length}")]
@@ -106,12 +130,15 @@ pub enum ParseColorError {
 
     #[error("Not a valid hexadecimal value")]
     InvalidHex(#[from] ParseIntError),
+
+    #[error("Expected ASCII hexadecimal digits")]
+    NonAscii,
 }
 
 #[cfg(test)]
 mod tests {
     use super::RgbColor;
-    use crate::color::{Color, ParseColorError, RgbaColor};
+    use crate::color::{blend, Color, ParseColorError, RgbaColor};
 
     #[test]
     fn test_parse_rgb() {
@@ -148,8 +175,26 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_non_ascii() {
+        assert_eq!("aé123".parse::<Color>(), Err(ParseColorError::NonAscii))
+    }
+
     #[test]
     fn test_parse_invalid_hex() {
         assert!("xxxxxx".parse::<Color>().is_err())
     }
+
+    #[test]
+    fn test_blend() {
+        let background = RgbColor::new(0, 0, 0);
+        let foreground = RgbColor::new(255, 128, 0);
+
+        assert_eq!(blend(background, foreground, 0), background);
+        assert_eq!(blend(background, foreground, 255), foreground);
+        assert_eq!(
+            blend(background, foreground, 128),
+            RgbColor::new(128, 64, 0)
+        );
+    }
 }
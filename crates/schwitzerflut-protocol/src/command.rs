@@ -8,7 +8,9 @@ use thiserror::Error;
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub enum Command {
     GetCanvasSize(GetCanvasSizeCommand),
+    GetPixel(GetPixelCommand),
     SetPixel(SetPixelCommand),
+    Help(HelpCommand),
 }
 
 impl FromStr for Command {
@@ -16,8 +18,13 @@ impl FromStr for Command {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            // `PX <x> <y>` reads a pixel, `PX <x> <y> <color>` sets it
+            s if s.starts_with("PX ") && s.split(' ').count() == 3 => {
+                Ok(Self::GetPixel(s.parse::<GetPixelCommand>()?))
+            }
             s if s.starts_with("PX") => Ok(Self::SetPixel(s.parse::<SetPixelCommand>()?)),
             "SIZE" => Ok(Self::GetCanvasSize(GetCanvasSizeCommand)),
+            "HELP" => Ok(Self::Help(HelpCommand)),
             _ => Err(Self::Err::UnknownCommand),
         }
     }
@@ -27,7 +34,9 @@ impl Display for Command {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::GetCanvasSize(cmd) => write!(f, "{cmd}"),
+            Self::GetPixel(cmd) => write!(f, "{cmd}"),
             Self::SetPixel(cmd) => write!(f, "{cmd}"),
+            Self::Help(cmd) => write!(f, "{cmd}"),
         }
     }
 }
@@ -39,6 +48,9 @@ pub enum ParseCommandError {
 
     #[error("Unable to parse SetPixel command: {0}")]
     ParseSetPixelCommand(#[from] ParseSetPixelCommandError),
+
+    #[error("Unable to parse GetPixel command: {0}")]
+    ParseGetPixelCommand(#[from] ParseGetPixelCommandError),
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
@@ -57,11 +69,9 @@ impl FromStr for SetPixelCommand {
     type Err = ParseSetPixelCommandError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if &s[0..3] != "PX " {
-            return Err(Self::Err::Syntax);
-        }
+        let s = s.strip_prefix("PX ").ok_or(Self::Err::Syntax)?;
 
-        let (coordinates, color) = s[3..].rsplit_once(' ').ok_or(Self::Err::Syntax)?;
+        let (coordinates, color) = s.rsplit_once(' ').ok_or(Self::Err::Syntax)?;
         let coordinates = coordinates.parse()?;
         let color = color.parse()?;
 
@@ -92,6 +102,42 @@ pub enum ParseSetPixelCommandError {
     ParseColorError(#[from] ParseColorError),
 }
 
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct GetPixelCommand {
+    pub coordinates: Coordinates,
+}
+
+impl GetPixelCommand {
+    pub fn new(coordinates: Coordinates) -> Self {
+        Self { coordinates }
+    }
+}
+
+impl FromStr for GetPixelCommand {
+    type Err = ParseGetPixelCommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coordinates = s.strip_prefix("PX ").ok_or(Self::Err::Syntax)?.parse()?;
+
+        Ok(Self { coordinates })
+    }
+}
+
+impl Display for GetPixelCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PX {}", self.coordinates)
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ParseGetPixelCommandError {
+    #[error("Invalid Syntax, Expected: 'PX <u32> <u32>")]
+    Syntax,
+
+    #[error("Unable to parse coordinates: {0}")]
+    ParseCoordinatesError(#[from] ParseCoordinatesError),
+}
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
 pub struct GetCanvasSizeCommand;
 
@@ -101,10 +147,22 @@ impl Display for GetCanvasSizeCommand {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub struct HelpCommand;
+
+impl Display for HelpCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HELP")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::color::{Color, RgbColor, RgbaColor};
-    use crate::command::{Command, GetCanvasSizeCommand, ParseCommandError, SetPixelCommand};
+    use crate::command::{
+        Command, GetCanvasSizeCommand, GetPixelCommand, HelpCommand, ParseCommandError,
+        SetPixelCommand,
+    };
     use crate::coordinates::Coordinates;
 
     #[test]
@@ -148,6 +206,36 @@ mod tests {
         )
     }
 
+    #[test]
+    pub fn test_parse_get_pixel() {
+        assert_eq!(
+            "PX 1337 42".parse(),
+            Ok(Command::GetPixel(GetPixelCommand {
+                coordinates: Coordinates { x: 1337, y: 42 }
+            }))
+        )
+    }
+
+    #[test]
+    pub fn test_parse_help() {
+        assert_eq!("HELP".parse(), Ok(Command::Help(HelpCommand)))
+    }
+
+    #[test]
+    pub fn test_parse_truncated_command() {
+        assert!("PX".parse::<Command>().is_err())
+    }
+
+    #[test]
+    pub fn test_display_roundtrip() {
+        let command = Command::SetPixel(SetPixelCommand::new(
+            Coordinates::new(1337, 42),
+            Color::Rgb(RgbColor::new(0xc0, 0xff, 0xee)),
+        ));
+
+        assert_eq!(command.to_string().parse(), Ok(command))
+    }
+
     #[test]
     pub fn test_parse_unknown_command() {
         assert_eq!(
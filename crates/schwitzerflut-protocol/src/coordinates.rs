@@ -32,7 +32,7 @@ impl FromStr for Coordinates {
 
 impl Display for Coordinates {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}, {}", self.x, self.y)
+        write!(f, "{} {}", self.x, self.y)
     }
 }
 
@@ -62,6 +62,13 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_display_roundtrip() {
+        let coordinates = Coordinates::new(1337, 42);
+
+        assert_eq!(coordinates.to_string().parse(), Ok(coordinates))
+    }
+
     #[test]
     fn test_parse_invalid_integer() {
         assert!("foobar 32".parse::<Coordinates>().is_err())
@@ -1,3 +1,6 @@
+#[cfg(feature = "canvas")]
+pub mod canvas;
 pub mod color;
 pub mod command;
 pub mod coordinates;
+pub mod response;
@@ -0,0 +1,172 @@
+use crate::color::{Color, ParseColorError};
+use crate::coordinates::{Coordinates, ParseCoordinatesError};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Line sent by a server in reply to a query command
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Response {
+    CanvasSize(CanvasSizeResponse),
+    Pixel(PixelResponse),
+}
+
+impl FromStr for Response {
+    type Err = ParseResponseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            s if s.starts_with("SIZE ") => Ok(Self::CanvasSize(s.parse()?)),
+            s if s.starts_with("PX ") => Ok(Self::Pixel(s.parse()?)),
+            _ => Err(Self::Err::UnknownResponse),
+        }
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CanvasSize(response) => write!(f, "{response}"),
+            Self::Pixel(response) => write!(f, "{response}"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ParseResponseError {
+    #[error("Unknown response")]
+    UnknownResponse,
+
+    #[error("Invalid Syntax, Expected: 'SIZE <u32> <u32>' or 'PX <u32> <u32> <hex color>'")]
+    Syntax,
+
+    #[error("Unable to parse integer: {0}")]
+    ParseIntError(#[from] ParseIntError),
+
+    #[error("Unable to parse coordinates: {0}")]
+    ParseCoordinatesError(#[from] ParseCoordinatesError),
+
+    #[error("Unable to parse color: {0}")]
+    ParseColorError(#[from] ParseColorError),
+}
+
+/// Reply to [`GetCanvasSizeCommand`](crate::command::GetCanvasSizeCommand)
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CanvasSizeResponse {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CanvasSizeResponse {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+}
+
+impl FromStr for CanvasSizeResponse {
+    type Err = ParseResponseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .strip_prefix("SIZE ")
+            .and_then(|s| s.split_once(' '))
+            .ok_or(Self::Err::Syntax)?;
+
+        Ok(Self {
+            width: width.parse()?,
+            height: height.parse()?,
+        })
+    }
+}
+
+impl Display for CanvasSizeResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SIZE {} {}", self.width, self.height)
+    }
+}
+
+/// Reply to [`GetPixelCommand`](crate::command::GetPixelCommand)
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PixelResponse {
+    pub coordinates: Coordinates,
+    pub color: Color,
+}
+
+impl PixelResponse {
+    pub fn new(coordinates: Coordinates, color: Color) -> Self {
+        Self { coordinates, color }
+    }
+}
+
+impl FromStr for PixelResponse {
+    type Err = ParseResponseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (coordinates, color) = s
+            .strip_prefix("PX ")
+            .and_then(|s| s.rsplit_once(' '))
+            .ok_or(Self::Err::Syntax)?;
+
+        Ok(Self {
+            coordinates: coordinates.parse()?,
+            color: color.parse()?,
+        })
+    }
+}
+
+impl Display for PixelResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PX {} {}", self.coordinates, self.color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::{Color, RgbColor};
+    use crate::coordinates::Coordinates;
+    use crate::response::{CanvasSizeResponse, ParseResponseError, PixelResponse, Response};
+
+    #[test]
+    fn test_parse_canvas_size() {
+        assert_eq!(
+            "SIZE 1920 1080".parse(),
+            Ok(Response::CanvasSize(CanvasSizeResponse {
+                width: 1920,
+                height: 1080
+            }))
+        )
+    }
+
+    #[test]
+    fn test_parse_pixel() {
+        assert_eq!(
+            "PX 1337 42 c0ffee".parse(),
+            Ok(Response::Pixel(PixelResponse {
+                coordinates: Coordinates { x: 1337, y: 42 },
+                color: Color::Rgb(RgbColor {
+                    r: 0xc0,
+                    g: 0xff,
+                    b: 0xee
+                })
+            }))
+        )
+    }
+
+    #[test]
+    fn test_parse_invalid_canvas_size() {
+        assert_eq!(
+            "SIZE 1920".parse::<Response>(),
+            Err(ParseResponseError::Syntax)
+        )
+    }
+
+    #[test]
+    fn test_parse_unknown_response() {
+        assert_eq!(
+            "ERROR".parse::<Response>(),
+            Err(ParseResponseError::UnknownResponse)
+        )
+    }
+}
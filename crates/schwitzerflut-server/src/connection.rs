@@ -0,0 +1,114 @@
+use schwitzerflut_protocol::canvas::Canvas;
+use schwitzerflut_protocol::command::Command;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+/// Longest line accepted before the connection is dropped
+const MAX_LINE_LENGTH: u64 = 64;
+
+const HELP: &str = "\
+HELP: PX <x> <y> <rrggbb|rrggbbaa> draws a pixel, blending colors with alpha
+HELP: PX <x> <y> replies with PX <x> <y> <rrggbb>
+HELP: SIZE replies with SIZE <width> <height>
+HELP: HELP prints this text
+";
+
+/// Executes the commands of a client until it disconnects.
+///
+/// Invalid lines are ignored without a reply, as clients that only draw never read from the
+/// connection. Replies are buffered and flushed once all received input is processed.
+pub fn serve(canvas: &Canvas, input: impl Read, output: impl Write) -> io::Result<()> {
+    let mut reader = BufReader::new(input);
+    let mut writer = BufWriter::new(output);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        let read = (&mut reader)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut line)?;
+
+        match line.last() {
+            None => break,
+            Some(b'\n') => {}
+            // line too long, or the client disconnected in the middle of it
+            Some(_) if read as u64 == MAX_LINE_LENGTH => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"))
+            }
+            Some(_) => break,
+        }
+
+        let command = std::str::from_utf8(&line)
+            .ok()
+            .and_then(|line| line.trim_end_matches(['\n', '\r']).parse::<Command>().ok());
+
+        match command {
+            Some(Command::Help(_)) => writer.write_all(HELP.as_bytes())?,
+            Some(command) => {
+                if let Some(response) = canvas.execute(&command) {
+                    writeln!(writer, "{response}")?;
+                }
+            }
+            None => {}
+        }
+
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connection::serve;
+    use schwitzerflut_protocol::canvas::Canvas;
+    use schwitzerflut_protocol::color::RgbColor;
+    use schwitzerflut_protocol::coordinates::Coordinates;
+
+    fn run(canvas: &Canvas, input: &str) -> String {
+        let mut output = Vec::new();
+        serve(canvas, input.as_bytes(), &mut output).unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_set_and_get_pixel() {
+        let canvas = Canvas::new(8, 8);
+
+        let output = run(&canvas, "PX 1 2 c0ffee\r\nPX 1 2\nPX 9 9\n");
+
+        assert_eq!(
+            canvas.get(Coordinates::new(1, 2)),
+            Some(RgbColor::new(0xc0, 0xff, 0xee))
+        );
+        assert_eq!(output, "PX 1 2 c0ffee\n");
+    }
+
+    #[test]
+    fn test_size() {
+        assert_eq!(run(&Canvas::new(800, 600), "SIZE\n"), "SIZE 800 600\n")
+    }
+
+    #[test]
+    fn test_ignore_invalid_lines() {
+        let canvas = Canvas::new(8, 8);
+
+        let output = run(&canvas, "PX\nPX 1, 1 ffffff\n\u{e9}\nSIZE\n");
+
+        assert_eq!(output, "SIZE 8 8\n");
+        assert_eq!(
+            canvas.get(Coordinates::new(1, 1)),
+            Some(RgbColor::new(0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_reject_long_lines() {
+        let canvas = Canvas::new(8, 8);
+        let line = "x".repeat(100);
+
+        assert!(serve(&canvas, line.as_bytes(), Vec::new()).is_err())
+    }
+}
@@ -0,0 +1,102 @@
+mod connection;
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use image::{ImageFormat, RgbaImage};
+use schwitzerflut_protocol::canvas::Canvas;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Reference Pixelflut server
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Address to listen on
+    #[arg(short, long, env, default_value = "0.0.0.0:1337")]
+    listen: SocketAddr,
+
+    /// Width of the canvas
+    #[arg(long, env, default_value_t = 1280)]
+    width: u32,
+
+    /// Height of the canvas
+    #[arg(long, env, default_value_t = 720)]
+    height: u32,
+
+    /// Periodically save the canvas as PNG to this path
+    #[arg(long, env)]
+    snapshot: Option<PathBuf>,
+
+    /// Time between two snapshots, e.g. "10s" or "1m"
+    #[arg(long, env, default_value = "10s", value_parser = humantime::parse_duration)]
+    snapshot_interval: Duration,
+}
+
+/// Writes the canvas to a temporary file first, so the snapshot is never seen half written
+fn save_snapshot(canvas: &Canvas, path: &Path) -> anyhow::Result<()> {
+    let temporary = path.with_extension("png.tmp");
+
+    let image = RgbaImage::from_raw(canvas.width(), canvas.height(), canvas.to_rgba_bytes())
+        .ok_or_else(|| anyhow!("canvas does not match its size"))?;
+
+    image
+        .save_with_format(&temporary, ImageFormat::Png)
+        .with_context(|| format!("unable to write {}", temporary.display()))?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("unable to move snapshot to {}", path.display()))
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let canvas = Arc::new(Canvas::new(cli.width, cli.height));
+    let listener = TcpListener::bind(cli.listen)
+        .with_context(|| format!("unable to listen on {}", cli.listen))?;
+
+    println!(
+        "serving a {}x{} canvas on {}",
+        cli.width, cli.height, cli.listen
+    );
+
+    if let Some(path) = cli.snapshot {
+        let canvas = canvas.clone();
+
+        std::thread::spawn(move || loop {
+            std::thread::sleep(cli.snapshot_interval);
+
+            if let Err(e) = save_snapshot(&canvas, &path) {
+                eprintln!("{:#}", e);
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("unable to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        let canvas = canvas.clone();
+
+        std::thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map_or_else(|_| "unknown peer".to_string(), |addr| addr.to_string());
+
+            let result = stream
+                .try_clone()
+                .and_then(|output| connection::serve(&canvas, &stream, output));
+
+            if let Err(e) = result {
+                eprintln!("{}: {}", peer, e);
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -67,6 +67,13 @@
                 inherit cargoArtifacts;
               });
 
+              server = craneLib.buildPackage (commonArgs // {
+                pname = "server";
+                cargoExtraFlags = "-p schwitzerflut-server";
+                meta.mainProgram = "schwitzerflut-server";
+                inherit cargoArtifacts;
+              });
+
               rustdoc = craneLib.cargoDoc (commonArgs // { inherit cargoArtifacts; });
             };
 